@@ -0,0 +1,206 @@
+use axum::{
+    Json,
+    extract::{rejection::JsonRejection, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use std::sync::Arc;
+use tracing::{info, error};
+
+use crate::admin::model::{AddKeyRequest, CreatedClient, KeyringStatus};
+use crate::auth::clients::{ClientSummary, NewClient};
+use crate::auth::keyring::{KeyMaterial, KeySource, SigningKey};
+use crate::auth::model::AppConfig;
+use crate::error::error::AppError;
+use crate::format::allowlist::ResendRouteConfig;
+use crate::format::credentials::CredentialSummary;
+use crate::models::response::ApiResponse;
+use crate::services::key_rotation::reload_keyring_file;
+use crate::services::refresh::RefreshStatus;
+
+// Admin endpoints authenticate with the `X-Admin-Key` header and are disabled
+// entirely unless ADMIN_API_KEY is configured
+pub fn require_admin(config: &AppConfig, headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(expected) = config.admin_api_key.as_deref() else {
+        error!("Admin endpoint called but ADMIN_API_KEY is not configured");
+        return Err(AppError::NotFound("Admin API is disabled".to_string()));
+    };
+
+    // Digests are compared in constant time, so timing reveals nothing about the key
+    let provided = headers.get("x-admin-key").map(|v| openssl::sha::sha256(v.as_bytes()));
+    if !provided.is_some_and(|provided| openssl::memcmp::eq(&provided, &openssl::sha::sha256(expected.as_bytes()))) {
+        error!("Unauthorized admin request");
+        return Err(AppError::Unauthorized("Invalid admin key".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn token_refresh_status(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<Vec<RefreshStatus>>, AppError> {
+    require_admin(&config, &headers)?;
+    info!("Returning background token refresh status");
+    Ok(ApiResponse::new(config.token_refresher.statuses()))
+}
+
+fn keyring_status(config: &AppConfig, now: i64) -> KeyringStatus {
+    KeyringStatus {
+        active_kid: config.keyring.active_kid(),
+        grace_seconds: config.keyring.grace_seconds,
+        keys: config.keyring.summaries(now),
+        events: config.keyring.history(),
+    }
+}
+
+pub async fn list_keys(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<KeyringStatus>, AppError> {
+    require_admin(&config, &headers)?;
+    let now = config.clock.now();
+    Ok(ApiResponse::new(keyring_status(&config, now)))
+}
+
+pub async fn add_key(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    payload: Result<Json<AddKeyRequest>, JsonRejection>,
+) -> Result<(StatusCode, ApiResponse<KeyringStatus>), AppError> {
+    require_admin(&config, &headers)?;
+    let Json(payload) = payload.map_err(|e| {
+        error!("Invalid add key request body: {}", e);
+        AppError::from(e)
+    })?;
+    let now = config.clock.now();
+    info!("Adding signing key {} via admin API", payload.kid);
+
+    let app_id = payload.app_id.as_deref().or(config.key_defaults.app_id.as_deref()).unwrap_or_default();
+    let region = payload.region.resolve(&config.key_defaults.region)
+        .map_err(AppError::validation)?;
+    let material = KeyMaterial {
+        pem: payload.private_key,
+        passphrase: payload.passphrase,
+        algorithm: payload.algorithm,
+    };
+    let key = SigningKey::from_pem(&payload.kid, &material, app_id, region, KeySource::Admin, now)
+        .map_err(AppError::validation)?;
+    config.keyring.insert(key, now);
+    if payload.activate {
+        config.keyring.activate(&payload.kid, KeySource::Admin, now)
+            .map_err(AppError::validation)?;
+    }
+    Ok((StatusCode::CREATED, ApiResponse::new(keyring_status(&config, now))))
+}
+
+pub async fn activate_key(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    Path(kid): Path<String>,
+) -> Result<ApiResponse<KeyringStatus>, AppError> {
+    require_admin(&config, &headers)?;
+    let now = config.clock.now();
+    info!("Activating signing key {} via admin API", kid);
+
+    config.keyring.activate(&kid, KeySource::Admin, now)
+        .map_err(AppError::validation)?;
+    Ok(ApiResponse::new(keyring_status(&config, now)))
+}
+
+pub async fn retire_key(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    Path(kid): Path<String>,
+) -> Result<ApiResponse<KeyringStatus>, AppError> {
+    require_admin(&config, &headers)?;
+    let now = config.clock.now();
+    info!("Retiring signing key {} via admin API", kid);
+
+    config.keyring.retire(&kid, KeySource::Admin, now)
+        .map_err(AppError::validation)?;
+    Ok(ApiResponse::new(keyring_status(&config, now)))
+}
+
+pub async fn reload_keys(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<KeyringStatus>, AppError> {
+    require_admin(&config, &headers)?;
+    info!("Reloading keyring file via admin API");
+
+    reload_keyring_file(&config).map_err(|e| {
+        error!("Keyring reload failed: {}", e);
+        AppError::Config(e)
+    })?;
+    let now = config.clock.now();
+    Ok(ApiResponse::new(keyring_status(&config, now)))
+}
+
+pub async fn list_clients(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<Vec<ClientSummary>>, AppError> {
+    require_admin(&config, &headers)?;
+    Ok(ApiResponse::new(config.clients.list()))
+}
+
+pub async fn create_client(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    payload: Result<Json<NewClient>, JsonRejection>,
+) -> Result<(StatusCode, ApiResponse<CreatedClient>), AppError> {
+    require_admin(&config, &headers)?;
+    let Json(payload) = payload.map_err(|e| {
+        error!("Invalid create client request body: {}", e);
+        AppError::from(e)
+    })?;
+    let now = config.clock.now();
+    info!("Creating API client {} via admin API", payload.name);
+
+    let (client, api_key) = config.clients.create(payload, now)
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::CREATED, ApiResponse::new(CreatedClient { client, api_key })))
+}
+
+pub async fn revoke_client(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<ApiResponse<ClientSummary>, AppError> {
+    require_admin(&config, &headers)?;
+    let now = config.clock.now();
+    info!("Revoking API client {} via admin API", id);
+
+    let client = config.clients.revoke(&id, now)
+        .map_err(AppError::NotFound)?;
+    Ok(ApiResponse::new(client))
+}
+
+pub async fn list_resend_routes(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<Vec<ResendRouteConfig>>, AppError> {
+    require_admin(&config, &headers)?;
+    Ok(ApiResponse::new(config.resend_routes.list()))
+}
+
+pub async fn reload_resend_routes(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<Vec<ResendRouteConfig>>, AppError> {
+    require_admin(&config, &headers)?;
+    info!("Reloading resend routes via admin API");
+
+    config.resend_routes.reload().map_err(|e| {
+        error!("Resend routes reload failed: {}", e);
+        AppError::Config(e)
+    })?;
+    Ok(ApiResponse::new(config.resend_routes.list()))
+}
+
+pub async fn list_credentials(
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+) -> Result<ApiResponse<Vec<CredentialSummary>>, AppError> {
+    require_admin(&config, &headers)?;
+    Ok(ApiResponse::new(config.credentials.list()))
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth::clients::ClientSummary;
+use crate::auth::keyring::{KeyRotationEvent, KeySummary};
+use crate::auth::region::RegionConfig;
+
+// Body of POST /admin/keys (no Debug, it carries a private key)
+#[derive(Deserialize)]
+pub struct AddKeyRequest {
+    pub kid: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub passphrase: Option<String>, // For encrypted PEMs
+    #[serde(default)]
+    pub algorithm: Option<String>, // Detected from the key when omitted
+    #[serde(default)]
+    pub app_id: Option<String>, // Falls back to COZE_APP_ID
+    #[serde(flatten)]
+    pub region: RegionConfig, // Falls back to COZE_REGION
+    #[serde(default)]
+    pub activate: bool,
+}
+
+// Response of GET /admin/keys
+#[derive(Debug, Serialize)]
+pub struct KeyringStatus {
+    pub active_kid: Option<String>,
+    pub grace_seconds: i64,
+    pub keys: Vec<KeySummary>,
+    pub events: Vec<KeyRotationEvent>,
+}
+
+// Response of POST /admin/clients; the only time the API key is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedClient {
+    pub client: ClientSummary,
+    pub api_key: String,
+}
@@ -0,0 +1,183 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk, KeyAlgorithm,
+    OctetKeyPairParameters, PublicKeyUse, RSAKeyParameters,
+};
+use openssl::{bn::{BigNum, BigNumContext}, nid::Nid, pkey::{Id, PKey, Private}};
+use std::str::FromStr;
+
+// Kind of private key found in a PEM, which decides the algorithms it can sign with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Rsa,
+    EcP256,
+    Ed25519,
+}
+
+impl KeyType {
+    pub fn detect(pkey: &PKey<Private>) -> Result<Self, String> {
+        match pkey.id() {
+            Id::RSA => Ok(KeyType::Rsa),
+            Id::EC => {
+                let curve = pkey.ec_key().map_err(|e| e.to_string())?.group().curve_name();
+                match curve {
+                    Some(Nid::X9_62_PRIME256V1) => Ok(KeyType::EcP256),
+                    other => Err(format!("Unsupported EC curve {:?} (only P-256 is supported)", other)),
+                }
+            }
+            Id::ED25519 => Ok(KeyType::Ed25519),
+            other => Err(format!("Unsupported private key type {:?}", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyType::Rsa => "RSA",
+            KeyType::EcP256 => "EC P-256",
+            KeyType::Ed25519 => "Ed25519",
+        }
+    }
+
+    // Used when a key does not name its algorithm
+    pub fn default_algorithm(&self) -> Algorithm {
+        match self {
+            KeyType::Rsa => Algorithm::RS256,
+            KeyType::EcP256 => Algorithm::ES256,
+            KeyType::Ed25519 => Algorithm::EdDSA,
+        }
+    }
+
+    pub fn supports(&self, algorithm: Algorithm) -> bool {
+        match self {
+            KeyType::Rsa => matches!(algorithm,
+                Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+                | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512),
+            KeyType::EcP256 => algorithm == Algorithm::ES256,
+            KeyType::Ed25519 => algorithm == Algorithm::EdDSA,
+        }
+    }
+}
+
+// Accepts PEMs whose newlines were escaped as "\n" (as env files and JSON
+// often store them), surrounding quotes, and CRLF line endings
+pub fn normalize_pem(pem: &str) -> String {
+    pem.trim()
+        .trim_matches('"')
+        .replace("\\r\\n", "\n")
+        .replace("\\n", "\n")
+        .replace("\r\n", "\n")
+        .trim()
+        .to_string()
+}
+
+// Parses a PKCS#1, SEC1 or PKCS#8 PEM, decrypting it when it is encrypted
+pub fn parse_private_key(pem: &str, passphrase: Option<&str>) -> Result<PKey<Private>, String> {
+    let encrypted = pem.contains("ENCRYPTED");
+    match passphrase {
+        Some(passphrase) => PKey::private_key_from_pem_passphrase(pem.as_bytes(), passphrase.as_bytes())
+            .map_err(|e| format!("Failed to decrypt private key (wrong passphrase?): {}", e)),
+        None if encrypted => Err("The private key is encrypted but no passphrase was configured".to_string()),
+        None => PKey::private_key_from_pem(pem.as_bytes())
+            .map_err(|e| format!("Failed to parse private key: {}", e)),
+    }
+}
+
+// A parsed private key, ready to sign, together with its public half
+pub struct LoadedKey {
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub public_jwk: Jwk,
+    pub public_key_pem: String, // SPKI PEM, the format the Coze console asks for
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::HS256 => KeyAlgorithm::HS256,
+        Algorithm::HS384 => KeyAlgorithm::HS384,
+        Algorithm::HS512 => KeyAlgorithm::HS512,
+        Algorithm::ES256 => KeyAlgorithm::ES256,
+        Algorithm::ES384 => KeyAlgorithm::ES384,
+        Algorithm::RS256 => KeyAlgorithm::RS256,
+        Algorithm::RS384 => KeyAlgorithm::RS384,
+        Algorithm::RS512 => KeyAlgorithm::RS512,
+        Algorithm::PS256 => KeyAlgorithm::PS256,
+        Algorithm::PS384 => KeyAlgorithm::PS384,
+        Algorithm::PS512 => KeyAlgorithm::PS512,
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+    }
+}
+
+// Public key parameters as RFC 7518 encodes them: base64url without padding,
+// EC coordinates left-padded to the curve size
+fn public_jwk(pkey: &PKey<Private>, key_type: KeyType, algorithm: Algorithm, kid: &str) -> Result<Jwk, String> {
+    let encode = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+    let parameters = match key_type {
+        KeyType::Rsa => {
+            let rsa = pkey.rsa().map_err(|e| e.to_string())?;
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: Default::default(),
+                n: encode(&rsa.n().to_vec()),
+                e: encode(&rsa.e().to_vec()),
+            })
+        }
+        KeyType::EcP256 => {
+            let ec_key = pkey.ec_key().map_err(|e| e.to_string())?;
+            let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+            let mut x = BigNum::new().map_err(|e| e.to_string())?;
+            let mut y = BigNum::new().map_err(|e| e.to_string())?;
+            ec_key.public_key().affine_coordinates(ec_key.group(), &mut x, &mut y, &mut ctx).map_err(|e| e.to_string())?;
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: Default::default(),
+                curve: EllipticCurve::P256,
+                x: encode(&x.to_vec_padded(32).map_err(|e| e.to_string())?),
+                y: encode(&y.to_vec_padded(32).map_err(|e| e.to_string())?),
+            })
+        }
+        KeyType::Ed25519 => AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: Default::default(),
+            curve: EllipticCurve::Ed25519,
+            x: encode(&pkey.raw_public_key().map_err(|e| e.to_string())?),
+        }),
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm(algorithm)),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
+
+// Detects the key type of `pem`, checks it against the configured algorithm
+// (if any) and returns the algorithm with a matching jsonwebtoken key
+pub fn load_signing_key(kid: &str, pem: &str, passphrase: Option<&str>, algorithm: Option<&str>) -> Result<LoadedKey, String> {
+    let pkey = parse_private_key(pem, passphrase)?;
+    let key_type = KeyType::detect(&pkey)?;
+
+    let algorithm = match algorithm {
+        Some(name) => Algorithm::from_str(name).map_err(|_| format!("Unknown algorithm {}", name))?,
+        None => key_type.default_algorithm(),
+    };
+    if !key_type.supports(algorithm) {
+        return Err(format!("Algorithm {:?} cannot be used with an {} key", algorithm, key_type.name()));
+    }
+
+    // jsonwebtoken only reads EC and Ed25519 keys as PKCS#8, so re-encode them
+    let pkcs8 = pkey.private_key_to_pem_pkcs8().map_err(|e| e.to_string())?;
+    let encoding_key = match key_type {
+        KeyType::Rsa => EncodingKey::from_rsa_pem(&pkcs8),
+        KeyType::EcP256 => EncodingKey::from_ec_pem(&pkcs8),
+        KeyType::Ed25519 => EncodingKey::from_ed_pem(&pkcs8),
+    }
+    .map_err(|e| format!("Failed to create encoding key: {}", e))?;
+
+    let public_jwk = public_jwk(&pkey, key_type, algorithm, kid)?;
+    let public_key_pem = String::from_utf8(pkey.public_key_to_pem().map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+
+    Ok(LoadedKey { algorithm, encoding_key, public_jwk, public_key_pem })
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::debug;
+
+use crate::auth::model::{CozeTokenResponse, SessionClaims, TokenScope};
+
+// Identifies one cached Coze access token: the signing key id plus the
+// duration it is minted for, and the session claims and scope it was issued for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub kid: String,
+    pub duration_seconds: u64, // See TokenCache::mint_duration
+    pub session_name: Option<String>,
+    pub extra_claims: String, // Serialized claims; serde_json keeps object keys sorted
+    pub scope: Option<TokenScope>, // Sorted, so equal scopes share a token
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedToken {
+    pub response: CozeTokenResponse,
+    pub expires_at: i64, // Unix timestamp (seconds)
+}
+
+// Each key owns an async mutex; whoever holds it performs the upstream
+// exchange while concurrent callers for the same key wait and reuse the result
+pub type CacheSlot = Arc<AsyncMutex<Option<CachedToken>>>;
+
+pub struct TokenCache {
+    slots: Mutex<HashMap<CacheKey, CacheSlot>>,
+    pub refresh_margin_seconds: i64,
+    pub bucket_seconds: u64,
+    max_entries: usize, // Callers choose session names, so the key space is unbounded
+}
+
+// The expiry of a slot nobody is using (empty slots sort first), or None while
+// a caller holds it or is filling it
+fn idle_expiry(slot: &CacheSlot) -> Option<i64> {
+    if Arc::strong_count(slot) > 1 {
+        return None;
+    }
+    let cached = slot.try_lock().ok()?;
+    Some(cached.as_ref().map_or(i64::MIN, |cached| cached.expires_at))
+}
+
+// Drops idle slots whose token has expired, then, while more than `keep`
+// remain, the idle ones expiring soonest. Returns how many were dropped.
+fn evict(slots: &mut HashMap<CacheKey, CacheSlot>, now: i64, keep: usize) -> usize {
+    let before = slots.len();
+    slots.retain(|_, slot| idle_expiry(slot).is_none_or(|expires_at| expires_at > now));
+
+    if slots.len() > keep {
+        let mut idle: Vec<(i64, CacheKey)> = slots.iter()
+            .filter_map(|(key, slot)| idle_expiry(slot).map(|expires_at| (expires_at, key.clone())))
+            .collect();
+        idle.sort_by_key(|(expires_at, _)| *expires_at);
+        let excess = slots.len() - keep;
+        for (_, key) in idle.into_iter().take(excess) {
+            slots.remove(&key);
+        }
+    }
+    before - slots.len()
+}
+
+impl TokenCache {
+    pub fn new(refresh_margin_seconds: i64, bucket_seconds: u64, max_entries: usize) -> Self {
+        TokenCache {
+            slots: Mutex::new(HashMap::new()),
+            refresh_margin_seconds,
+            bucket_seconds: bucket_seconds.max(1),
+            max_entries: max_entries.max(1),
+        }
+    }
+
+    // Requested durations are rounded up to the bucket's upper bound, so 3500s
+    // and 3600s share a token that lasts for either, but never beyond the
+    // caller's maximum, so a client is not served a token outliving its limit
+    pub fn mint_duration(&self, duration_seconds: u64, max_duration_seconds: u64) -> u64 {
+        duration_seconds.div_ceil(self.bucket_seconds)
+            .saturating_mul(self.bucket_seconds)
+            .min(max_duration_seconds)
+            .max(duration_seconds)
+    }
+
+    pub fn key(&self, kid: &str, duration_seconds: u64, max_duration_seconds: u64, session: &SessionClaims, scope: Option<&TokenScope>) -> CacheKey {
+        CacheKey {
+            kid: kid.to_string(),
+            duration_seconds: self.mint_duration(duration_seconds, max_duration_seconds),
+            session_name: session.session_name.clone(),
+            extra_claims: serde_json::Value::Object(session.extra.clone()).to_string(),
+            scope: scope.map(|scope| {
+                let mut scope = scope.clone();
+                scope.permissions.sort();
+                scope.permissions.dedup();
+                scope.bot_ids.sort();
+                scope.bot_ids.dedup();
+                scope
+            }),
+        }
+    }
+
+    // The slot for `key`. Adding a new one first makes room: expired slots are
+    // dropped and, at max_entries, the ones expiring soonest.
+    pub fn slot(&self, key: CacheKey, now: i64) -> CacheSlot {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(slot) = slots.get(&key) {
+            return slot.clone();
+        }
+        let evicted = evict(&mut slots, now, self.max_entries - 1);
+        if evicted > 0 {
+            debug!("Evicted {} cached Coze token slot(s)", evicted);
+        }
+        slots.entry(key).or_default().clone()
+    }
+
+    // Drops idle slots whose token has expired
+    pub fn prune(&self, now: i64) -> usize {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        evict(&mut slots, now, usize::MAX)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A cached token is served until `refresh_margin_seconds` before it expires
+    pub fn is_fresh(&self, cached: &CachedToken, now: i64) -> bool {
+        now < cached.expires_at - self.refresh_margin_seconds
+    }
+}
+
+// Coze returns `expires_in` as an absolute Unix timestamp; tolerate a relative
+// number of seconds as well in case the upstream behaviour changes
+pub fn expires_at(now: i64, expires_in: i64) -> i64 {
+    if expires_in > now {
+        expires_in
+    } else {
+        now + expires_in
+    }
+}
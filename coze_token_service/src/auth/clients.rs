@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use tracing::{info, error};
+use uuid::Uuid;
+
+// Routes a client may be granted access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientRoute {
+    Token,
+    Resend,
+    Debug, // /token/jwt and /token/verify, when DEBUG_TOKEN_ENDPOINTS is enabled
+}
+
+fn default_routes() -> Vec<ClientRoute> {
+    vec![ClientRoute::Token]
+}
+
+// A caller of this service, identified by its own API key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiClient {
+    pub id: String,
+    pub name: String,
+    pub key_hash: String, // Hex SHA-256 of the API key; the key itself is never stored
+    #[serde(default)]
+    pub allowed_kids: Option<Vec<String>>, // None allows every configured kid
+    #[serde(default)]
+    pub max_duration_seconds: Option<u64>,
+    #[serde(default = "default_routes")]
+    pub allowed_routes: Vec<ClientRoute>,
+    #[serde(default)]
+    pub allowed_extra_claims: Vec<String>, // JWT claims the client may set through extra_claims
+    #[serde(default)]
+    pub allowed_permissions: Option<Vec<String>>, // None allows unscoped tokens; otherwise every token is scoped
+    #[serde(default)]
+    pub allowed_bot_ids: Option<Vec<String>>, // None allows any bot id in a requested scope
+    pub created_at: i64,
+    #[serde(default)]
+    pub revoked_at: Option<i64>,
+    #[serde(skip)]
+    pub builtin: bool, // Derived from EXPECTED_COZE_API_KEY, never written to the clients file
+}
+
+impl ApiClient {
+    pub fn allows_kid(&self, kid: &str) -> bool {
+        self.allowed_kids.as_ref().is_none_or(|kids| kids.iter().any(|k| k == kid))
+    }
+
+    pub fn allows_route(&self, route: ClientRoute) -> bool {
+        self.allowed_routes.contains(&route)
+    }
+
+    pub fn allows_extra_claim(&self, name: &str) -> bool {
+        self.allowed_extra_claims.iter().any(|claim| claim == name)
+    }
+
+    pub fn allows_permission(&self, permission: &str) -> bool {
+        self.allowed_permissions.as_ref().is_none_or(|permissions| permissions.iter().any(|p| p == permission))
+    }
+
+    pub fn allows_bot_id(&self, bot_id: &str) -> bool {
+        self.allowed_bot_ids.as_ref().is_none_or(|bot_ids| bot_ids.iter().any(|b| b == bot_id))
+    }
+}
+
+// What the admin API shows for a client
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientSummary {
+    pub id: String,
+    pub name: String,
+    pub allowed_kids: Option<Vec<String>>,
+    pub max_duration_seconds: Option<u64>,
+    pub allowed_routes: Vec<ClientRoute>,
+    pub allowed_extra_claims: Vec<String>,
+    pub allowed_permissions: Option<Vec<String>>,
+    pub allowed_bot_ids: Option<Vec<String>>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+    pub builtin: bool,
+}
+
+impl From<&ApiClient> for ClientSummary {
+    fn from(client: &ApiClient) -> Self {
+        ClientSummary {
+            id: client.id.clone(),
+            name: client.name.clone(),
+            allowed_kids: client.allowed_kids.clone(),
+            max_duration_seconds: client.max_duration_seconds,
+            allowed_routes: client.allowed_routes.clone(),
+            allowed_extra_claims: client.allowed_extra_claims.clone(),
+            allowed_permissions: client.allowed_permissions.clone(),
+            allowed_bot_ids: client.allowed_bot_ids.clone(),
+            created_at: client.created_at,
+            revoked_at: client.revoked_at,
+            builtin: client.builtin,
+        }
+    }
+}
+
+// Body of POST /admin/clients
+#[derive(Debug, Deserialize)]
+pub struct NewClient {
+    pub name: String,
+    #[serde(default)]
+    pub allowed_kids: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_duration_seconds: Option<u64>,
+    #[serde(default = "default_routes")]
+    pub allowed_routes: Vec<ClientRoute>,
+    #[serde(default)]
+    pub allowed_extra_claims: Vec<String>,
+    #[serde(default)]
+    pub allowed_permissions: Option<Vec<String>>,
+    #[serde(default)]
+    pub allowed_bot_ids: Option<Vec<String>>,
+}
+
+// Layout of the JSON file referenced by CLIENTS_FILE
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ClientsFile {
+    pub clients: Vec<ApiClient>,
+}
+
+#[derive(Debug)]
+pub enum ClientAuthError {
+    Unauthorized,
+    Forbidden(String),
+}
+
+pub fn hash_api_key(api_key: &str) -> String {
+    openssl::sha::sha256(api_key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn generate_api_key() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).map_err(|e| format!("Failed to generate API key: {}", e))?;
+    Ok(format!("cts_{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+}
+
+// All clients allowed to call this service. When backed by a file, every
+// change made through the admin API is written back to it.
+pub struct ClientRegistry {
+    clients: RwLock<Vec<ApiClient>>,
+    file: Option<String>,
+}
+
+impl ClientRegistry {
+    pub fn load(file: Option<String>) -> Result<Self, String> {
+        let clients = match file.as_deref() {
+            Some(path) if std::path::Path::new(path).exists() => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read clients file {}: {}", path, e))?;
+                serde_json::from_str::<ClientsFile>(&contents)
+                    .map_err(|e| format!("Failed to parse clients file {}: {}", path, e))?
+                    .clients
+            }
+            _ => Vec::new(),
+        };
+        info!("Loaded {} API client(s)", clients.len());
+
+        Ok(ClientRegistry {
+            clients: RwLock::new(clients),
+            file,
+        })
+    }
+
+    // Registers the shared EXPECTED_COZE_API_KEY as an unrestricted client
+    pub fn register_builtin(&self, api_key: &str, now: i64) {
+        let mut clients = self.clients.write().unwrap_or_else(|e| e.into_inner());
+        clients.push(ApiClient {
+            id: "default".to_string(),
+            name: "EXPECTED_COZE_API_KEY".to_string(),
+            key_hash: hash_api_key(api_key),
+            allowed_kids: None,
+            max_duration_seconds: None,
+            allowed_routes: vec![ClientRoute::Token, ClientRoute::Resend],
+            allowed_extra_claims: Vec::new(),
+            allowed_permissions: None,
+            allowed_bot_ids: None,
+            created_at: now,
+            revoked_at: None,
+            builtin: true,
+        });
+    }
+
+    pub fn authenticate(&self, api_key: &str, route: ClientRoute) -> Result<ApiClient, ClientAuthError> {
+        let key_hash = hash_api_key(api_key);
+        let clients = self.clients.read().unwrap_or_else(|e| e.into_inner());
+        let client = clients.iter()
+            .find(|client| client.key_hash == key_hash && client.revoked_at.is_none())
+            .ok_or(ClientAuthError::Unauthorized)?;
+
+        if !client.allows_route(route) {
+            return Err(ClientAuthError::Forbidden(format!("Client {} may not call this route", client.id)));
+        }
+        Ok(client.clone())
+    }
+
+    pub fn list(&self) -> Vec<ClientSummary> {
+        self.clients.read().unwrap_or_else(|e| e.into_inner()).iter().map(ClientSummary::from).collect()
+    }
+
+    // Returns the new client together with its plain-text API key, which is not retrievable later
+    pub fn create(&self, new_client: NewClient, now: i64) -> Result<(ClientSummary, String), String> {
+        let api_key = generate_api_key()?;
+        let client = ApiClient {
+            id: Uuid::new_v4().to_string(),
+            name: new_client.name,
+            key_hash: hash_api_key(&api_key),
+            allowed_kids: new_client.allowed_kids,
+            max_duration_seconds: new_client.max_duration_seconds,
+            allowed_routes: new_client.allowed_routes,
+            allowed_extra_claims: new_client.allowed_extra_claims,
+            allowed_permissions: new_client.allowed_permissions,
+            allowed_bot_ids: new_client.allowed_bot_ids,
+            created_at: now,
+            revoked_at: None,
+            builtin: false,
+        };
+        let summary = ClientSummary::from(&client);
+
+        // Persisted before it takes effect, so a failed write leaves no live client behind
+        let mut clients = self.clients.write().unwrap_or_else(|e| e.into_inner());
+        let mut updated = clients.clone();
+        updated.push(client);
+        self.persist(&updated)?;
+        *clients = updated;
+        info!("Created API client {} ({})", summary.id, summary.name);
+        Ok((summary, api_key))
+    }
+
+    pub fn revoke(&self, id: &str, now: i64) -> Result<ClientSummary, String> {
+        // Only takes effect once persisted, so a failed write is not undone by a restart
+        let mut clients = self.clients.write().unwrap_or_else(|e| e.into_inner());
+        let mut updated = clients.clone();
+        let client = updated.iter_mut()
+            .find(|client| client.id == id)
+            .ok_or_else(|| format!("Unknown client: {}", id))?;
+        if client.revoked_at.is_none() {
+            client.revoked_at = Some(now);
+        }
+        let summary = ClientSummary::from(&*client);
+
+        self.persist(&updated)?;
+        *clients = updated;
+        info!("Revoked API client {}", id);
+        Ok(summary)
+    }
+
+    // Writes through a temporary file so a crash never leaves a truncated registry
+    fn persist(&self, clients: &[ApiClient]) -> Result<(), String> {
+        let Some(path) = self.file.as_deref() else {
+            return Ok(());
+        };
+        let file = ClientsFile {
+            clients: clients.iter().filter(|client| !client.builtin).cloned().collect(),
+        };
+        let contents = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        let tmp_path = format!("{}.tmp", path);
+        std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| {
+                error!("Failed to write clients file {}: {}", path, e);
+                format!("Failed to write clients file: {}", e)
+            })
+    }
+}
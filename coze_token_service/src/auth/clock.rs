@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Source of the current time (Unix seconds) for JWT timestamps, cache expiry
+// and key grace windows, so tests can control it
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default()
+    }
+}
+
+// A clock that only moves when told to
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        ManualClock { now: AtomicI64::new(now) }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: i64) {
+        self.now.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+// Seconds our clock is ahead of an upstream HTTP `Date` header (negative when
+// behind), or None when the header is missing or malformed
+pub fn upstream_skew_seconds(now: i64, date: Option<&str>) -> Option<i64> {
+    let upstream = httpdate::parse_http_date(date?).ok()?;
+    let upstream = upstream.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    Some(now - upstream)
+}
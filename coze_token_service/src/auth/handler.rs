@@ -1,100 +1,363 @@
 use axum::{
     Json,
-    extract::State,
-    response::{Response, IntoResponse},
-    http::StatusCode,
+    extract::{State, rejection::JsonRejection},
+    http::{header::{DATE, RETRY_AFTER}, StatusCode},
 };
-use jsonwebtoken::{encode, Algorithm, Header};
-use std::{sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+use jsonwebtoken::{decode, decode_header, encode, jwk::JwkSet, DecodingKey, Header, Validation};
+use std::sync::Arc;
+use serde_json::{json, Map, Value};
 use uuid::Uuid;
-use tracing::{info, error, debug}; // Import tracing macros
+use tracing::{info, warn, error, debug}; // Import tracing macros
 
-use crate::auth::model::{AppConfig, TokenRequest, CozeTokenResponse, Claims, CozeTokenRequest};
+use crate::auth::cache::{expires_at, CachedToken};
+use crate::auth::clock::upstream_skew_seconds;
+use crate::auth::clients::{ApiClient, ClientAuthError, ClientRoute};
+use crate::auth::keyring::SigningKey;
+use crate::auth::upstream::CozeError;
+use crate::error::error::AppError;
+use crate::models::response::ApiResponse;
+use crate::auth::model::{AppConfig, AuthorizedTokenRequest, JwtDebugResponse, VerifyJwtRequest, VerifyJwtResponse, TokenRequest, CozeTokenResponse, Claims, CozeScope, CozeTokenRequest, SessionClaims, TokenScope, MAX_SESSION_NAME_LENGTH, RESERVED_CLAIMS};
 
 
 pub async fn generate_and_exchange_token(
     State(config): State<Arc<AppConfig>>,
-    Json(payload): Json<TokenRequest>,
-) -> Result<Json<CozeTokenResponse>, Response> {
+    payload: Result<Json<TokenRequest>, JsonRejection>,
+) -> Result<ApiResponse<CozeTokenResponse>, AppError> {
     info!("Received token generation and exchange request");
-    debug!("Request payload: {:?}", payload);
+    let Json(payload) = payload.map_err(|e| {
+        error!("Invalid token request body: {}", e);
+        AppError::from(e)
+    })?;
+    debug!("Request for kid {:?}, duration {:?}", payload.public_key, payload.duration_seconds);
 
-    // --- API Key Validation ---
-    if payload.coze_api_key != config.expected_coze_api_key {
-        error!("Unauthorized attempt with invalid coze_api_key");
-        return Err((StatusCode::UNAUTHORIZED, "Invalid coze_api_key").into_response());
+    let AuthorizedTokenRequest { signing_key, duration, max_duration, session, scope, now, .. } =
+        authorize_token_request(&config, payload, ClientRoute::Token)?;
+
+    // --- Token Cache Lookup ---
+    // Holding the slot lock across the exchange makes concurrent callers for
+    // the same key wait for (and then reuse) a single upstream round-trip
+    let cache_key = config.token_cache.key(&signing_key.kid, duration, max_duration, &session, scope.as_ref());
+    let mint_duration = cache_key.duration_seconds;
+    let slot = config.token_cache.slot(cache_key, now);
+    let mut cached = slot.lock().await;
+
+    if let Some(entry) = cached.as_ref().filter(|entry| config.token_cache.is_fresh(entry, now)) {
+        info!("Serving cached Coze access token (expires at {})", entry.expires_at);
+        return Ok(ApiResponse::new(entry.response.clone()));
     }
-    info!("API key validated successfully");
+    // --- End Token Cache Lookup ---
+
+    // Minted for the whole bucket, so later callers in it get the lifetime they ask for
+    let (upstream_status, coze_token_response) = exchange_token(&config, &signing_key, mint_duration, &session, scope.as_ref()).await?;
+
+    *cached = Some(CachedToken {
+        response: coze_token_response.clone(),
+        expires_at: expires_at(now, coze_token_response.expires_in),
+    });
+
+    // Return the response from Coze API
+    info!("Token generation and exchange successful");
+    Ok(ApiResponse::new(coze_token_response).with_upstream_status(upstream_status.as_u16()))
+}
+
+// Runs every policy check /token applies: caller, signing key, duration,
+// session claims and scope
+pub fn authorize_token_request(config: &AppConfig, payload: TokenRequest, route: ClientRoute) -> Result<AuthorizedTokenRequest, AppError> {
+    // --- API Key Validation ---
+    let client = authenticate_client(config, &payload.coze_api_key, route)?;
+    info!("API key validated successfully for client {}", client.id);
     // --- End API Key Validation ---
 
-    // --- Generate JWT ---
-    let mut header = Header::new(Algorithm::RS256);
-    header.kid = Some(payload.public_key.clone());
+    // --- Signing Key Lookup ---
+    // The requested kid must be one we hold a usable private key for, otherwise
+    // the JWT would be signed with a key Coze does not associate with that kid
+    let now = config.clock.now();
+    let signing_key = config.keyring.resolve(payload.public_key.as_deref(), now).map_err(|message| {
+        error!("Token request rejected: {}", message);
+        AppError::validation(message)
+    })?;
+    if !client.allows_kid(&signing_key.kid) {
+        error!("Client {} is not allowed to use kid {}", client.id, signing_key.kid);
+        return Err(AppError::Forbidden(format!("Client may not use public_key (kid): {}", signing_key.kid)));
+    }
+    // --- End Signing Key Lookup ---
+
+    // --- Duration Validation ---
+    // Default duration: the largest allowed (24 hours unless configured lower)
+    let bounds = config.duration_bounds.for_client(client.max_duration_seconds);
+    let duration = payload.duration_seconds.unwrap_or(bounds.max_seconds);
+    if !bounds.contains(duration) {
+        error!("Client {} requested {}s, outside {}..={}s", client.id, duration, bounds.min_seconds, bounds.max_seconds);
+        return Err(AppError::Validation {
+            message: format!("duration_seconds must be between {} and {}", bounds.min_seconds, bounds.max_seconds),
+            details: Some(json!({
+                "reason": "duration_out_of_range",
+                "requested": duration,
+                "min": bounds.min_seconds,
+                "max": bounds.max_seconds,
+            })),
+        });
+    }
+    // --- End Duration Validation ---
+
+    // --- Session Claims Validation ---
+    let session = session_claims(&client, payload.session_name, payload.extra_claims)?;
+    // --- End Session Claims Validation ---
+
+    // --- Scope Validation ---
+    let scope = token_scope(&client, payload.scope)?;
+    // --- End Scope Validation ---
+
+    Ok(AuthorizedTokenRequest { client, signing_key, duration, max_duration: bounds.max_seconds, session, scope, now })
+}
+
+// The debug endpoints look like they do not exist unless DEBUG_TOKEN_ENDPOINTS is set
+fn require_debug_endpoints(config: &AppConfig) -> Result<(), AppError> {
+    if config.debug_endpoints_enabled {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Not Found".to_string()))
+    }
+}
+
+// Signs the JWT /token would send to Coze and returns it without exchanging it
+pub async fn mint_jwt(
+    State(config): State<Arc<AppConfig>>,
+    payload: Result<Json<TokenRequest>, JsonRejection>,
+) -> Result<ApiResponse<JwtDebugResponse>, AppError> {
+    require_debug_endpoints(&config)?;
+    let Json(payload) = payload?;
+
+    let AuthorizedTokenRequest { client, signing_key, duration, session, scope, .. } =
+        authorize_token_request(&config, payload, ClientRoute::Debug)?;
+    info!("Minting debug JWT for client {} with kid {}", client.id, signing_key.kid);
+
+    let SignedJwt { token, header, claims } = sign_jwt(&config, &signing_key, duration, &session)?;
+    Ok(ApiResponse::new(JwtDebugResponse {
+        jwt: token,
+        header,
+        claims,
+        token_url: signing_key.region.token_url(),
+        exchange_request: coze_token_request(duration, scope.as_ref()),
+    }))
+}
+
+// Checks a JWT's signature, audience and issuer against our own keys
+pub async fn verify_jwt(
+    State(config): State<Arc<AppConfig>>,
+    payload: Result<Json<VerifyJwtRequest>, JsonRejection>,
+) -> Result<ApiResponse<VerifyJwtResponse>, AppError> {
+    require_debug_endpoints(&config)?;
+    let Json(payload) = payload?;
+    let client = authenticate_client(&config, &payload.coze_api_key, ClientRoute::Debug)?;
+    let now = config.clock.now();
+
+    let header = match decode_header(&payload.jwt) {
+        Ok(header) => header,
+        Err(e) => return Ok(ApiResponse::new(VerifyJwtResponse::invalid(None, format!("Malformed JWT: {}", e)))),
+    };
+    let Some(signing_key) = header.kid.as_deref().and_then(|kid| config.keyring.get(kid, now)) else {
+        let error = format!("Unknown kid: {}", header.kid.as_deref().unwrap_or("(none)"));
+        return Ok(ApiResponse::new(VerifyJwtResponse::invalid(Some(header), error)));
+    };
+    let decoding_key = DecodingKey::from_jwk(&signing_key.public_jwk)?;
+    let mut validation = Validation::new(signing_key.algorithm);
+    validation.set_audience(&[signing_key.region.audience()]);
+    validation.set_issuer(&[&signing_key.app_id]);
+
+    info!("Client {} verifying a JWT for kid {}", client.id, signing_key.kid);
+    Ok(ApiResponse::new(match decode::<Value>(&payload.jwt, &decoding_key, &validation) {
+        Ok(data) => VerifyJwtResponse {
+            valid: true,
+            kid: data.header.kid.clone(),
+            header: Some(data.header),
+            claims: Some(data.claims),
+            error: None,
+        },
+        Err(e) => VerifyJwtResponse::invalid(Some(header), e.to_string()),
+    }))
+}
+
+// Publishes the public half of every usable signing key; needs no credentials.
+// Served bare, not in an ApiResponse, since JWT libraries expect an RFC 7517 document.
+pub async fn jwks(State(config): State<Arc<AppConfig>>) -> Result<Json<JwkSet>, AppError> {
+    let now = config.clock.now();
+    Ok(Json(config.keyring.jwks(now)))
+}
+
+// Looks up the caller by API key and checks it may use `route`
+pub fn authenticate_client(config: &AppConfig, api_key: &str, route: ClientRoute) -> Result<ApiClient, AppError> {
+    config.clients.authenticate(api_key, route).map_err(|e| {
+        match &e {
+            ClientAuthError::Unauthorized => error!("Unauthorized attempt with invalid API key"),
+            ClientAuthError::Forbidden(message) => error!("{}", message),
+        }
+        AppError::from(e)
+    })
+}
+
+// Checks the caller's session name and extra claims against its client policy
+fn session_claims(client: &ApiClient, session_name: Option<String>, extra: Map<String, Value>) -> Result<SessionClaims, AppError> {
+    if let Some(name) = session_name.as_deref() {
+        if name.is_empty() || name.chars().count() > MAX_SESSION_NAME_LENGTH {
+            error!("Client {} sent an invalid session_name", client.id);
+            return Err(AppError::validation(format!("session_name must be 1 to {} characters", MAX_SESSION_NAME_LENGTH)));
+        }
+    }
+    for claim in extra.keys() {
+        if RESERVED_CLAIMS.contains(&claim.as_str()) {
+            error!("Client {} tried to override reserved claim {}", client.id, claim);
+            return Err(AppError::validation(format!("Claim {} is set by the service and cannot be overridden", claim)));
+        }
+        if !client.allows_extra_claim(claim) {
+            error!("Client {} is not allowed to set claim {}", client.id, claim);
+            return Err(AppError::Forbidden(format!("Client may not set claim: {}", claim)));
+        }
+    }
+    Ok(SessionClaims { session_name, extra })
+}
+
+// Checks a requested scope against the client's allowlist. Clients with a
+// permission or bot id allowlist never get an unscoped token: omitted parts of
+// the scope default to everything the client is allowed. A client limited only
+// by bot ids has no default permissions and must name them.
+fn token_scope(client: &ApiClient, requested: Option<TokenScope>) -> Result<Option<TokenScope>, AppError> {
+    let restricted = client.allowed_permissions.is_some() || client.allowed_bot_ids.is_some();
+    let Some(mut scope) = requested.or_else(|| restricted.then(TokenScope::default)) else {
+        return Ok(None);
+    };
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("System time error: {}", e)).into_response())?
-        .as_secs() as i64;
+    if scope.permissions.is_empty() {
+        scope.permissions = client.allowed_permissions.clone().unwrap_or_default();
+    }
+    if scope.permissions.is_empty() {
+        error!("Client {} sent no scope.permissions and has no default", client.id);
+        return Err(AppError::validation("scope.permissions must not be empty"));
+    }
+    if scope.bot_ids.is_empty() {
+        scope.bot_ids = client.allowed_bot_ids.clone().unwrap_or_default();
+    }
+    // An empty bot id list leaves bots unrestricted, which a bot allowlist must not
+    if client.allowed_bot_ids.is_some() && scope.bot_ids.is_empty() {
+        error!("Client {} has an empty bot id allowlist", client.id);
+        return Err(AppError::Forbidden("Client may not request a token for any bot".to_string()));
+    }
 
-    // Default duration: 24 hours (86400 seconds)
-    let duration = payload.duration_seconds.unwrap_or(86400);
+    if let Some(permission) = scope.permissions.iter().find(|p| !client.allows_permission(p)) {
+        error!("Client {} is not allowed permission {}", client.id, permission);
+        return Err(AppError::Forbidden(format!("Client may not request permission: {}", permission)));
+    }
+    if let Some(bot_id) = scope.bot_ids.iter().find(|b| !client.allows_bot_id(b)) {
+        error!("Client {} is not allowed bot id {}", client.id, bot_id);
+        return Err(AppError::Forbidden(format!("Client may not request bot id: {}", bot_id)));
+    }
+    Ok(Some(scope))
+}
+
+// A JWT as sent to Coze, with the header and claims it was built from
+pub struct SignedJwt {
+    pub token: String,
+    pub header: Header,
+    pub claims: Claims,
+}
+
+// Builds and signs the JWT Coze expects for a token exchange. `iat` is
+// back-dated by JWT_IAT_LEEWAY_SECONDS so a clock running slightly ahead of
+// Coze's does not produce a token "issued in the future".
+pub fn sign_jwt(config: &AppConfig, signing_key: &SigningKey, duration: u64, session: &SessionClaims) -> Result<SignedJwt, AppError> {
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = Some(signing_key.kid.clone());
+
+    let now = config.clock.now();
     let exp = now + duration as i64;
 
     let claims = Claims {
-        iat: now,
+        iat: now - config.iat_leeway_seconds,
         exp,
         jti: Uuid::new_v4().to_string(),
-        aud: "api.coze.cn".to_string(), // Keep audience as required by Coze
-        iss: payload.coze_api_key.clone(),
+        aud: signing_key.region.audience(), // Must match the region the JWT is exchanged in
+        iss: signing_key.app_id.clone(), // Coze expects the OAuth app id, never the caller's credential
+        session_name: session.session_name.clone(),
+        extra: session.extra.clone(),
     };
 
-    let jwt_token = encode(&header, &claims, &config.encoding_key)
+    let token = encode(&header, &claims, &signing_key.encoding_key)
         .map_err(|e| {
             error!("JWT encoding error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("JWT encoding error: {}", e)).into_response()
+            AppError::from(e)
         })?;
+    Ok(SignedJwt { token, header, claims })
+}
+
+fn coze_token_request(duration: u64, scope: Option<&TokenScope>) -> CozeTokenRequest {
+    CozeTokenRequest {
+        duration_seconds: duration, // Use the same duration
+        grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
+        scope: scope.map(CozeScope::from),
+    }
+}
+
+// Signs a fresh JWT with `signing_key` and exchanges it with Coze for an access
+// token, returned with the status Coze answered
+pub async fn exchange_token(
+    config: &AppConfig,
+    signing_key: &SigningKey,
+    duration: u64,
+    session: &SessionClaims,
+    scope: Option<&TokenScope>,
+) -> Result<(StatusCode, CozeTokenResponse), AppError> {
+    // --- Generate JWT ---
+    let SignedJwt { token: jwt_token, .. } = sign_jwt(config, signing_key, duration, session)?;
     debug!("Generated JWT: {}", jwt_token);
     // --- End Generate JWT ---
 
 
     // --- Exchange JWT for Coze Access Token ---
-    let coze_request_body = CozeTokenRequest {
-        duration_seconds: duration, // Use the same duration
-        grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
-    };
+    let coze_request_body = coze_token_request(duration, scope);
     debug!("Coze API request body: {:?}", coze_request_body);
-    info!("Calling Coze API at {}", config.coze_api_url);
+    let coze_api_url = signing_key.region.token_url();
+    info!("Calling Coze API at {}", coze_api_url);
 
     let coze_response = config.http_client
-        .post(&config.coze_api_url)
+        .post(&coze_api_url)
         .bearer_auth(&jwt_token)
         .json(&coze_request_body)
         .send()
         .await
         .map_err(|e| {
             error!("Failed to call Coze API: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to call Coze API: {}", e)).into_response()
+            AppError::from(e)
         })?;
 
     let status = coze_response.status();
     info!("Coze API responded with status: {}", status);
 
+    // Coze's Date header shows how far our clock is off, which is what makes
+    // it reject JWTs as issued in the future or already expired
+    let date = coze_response.headers().get(DATE).and_then(|v| v.to_str().ok());
+    if let Some(skew) = upstream_skew_seconds(config.clock.now(), date) {
+        if skew.abs() > config.clock_skew_warn_seconds {
+            warn!("Local clock is {}s {} Coze's; JWT timestamps may be rejected", skew.abs(), if skew > 0 { "ahead of" } else { "behind" });
+        }
+    }
+
     if !status.is_success() {
+        let logid = coze_response.headers().get("x-tt-logid").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let retry_after = coze_response.headers().get(RETRY_AFTER).cloned();
         let error_body = coze_response.text().await.unwrap_or_else(|_| "Failed to read Coze error body".to_string());
-        error!("Coze API returned error: {}", error_body);
-        return Err((StatusCode::BAD_GATEWAY, format!("Coze API returned error: {}", error_body)).into_response());
+        let coze_error = CozeError::from_upstream(status, logid, retry_after, &error_body);
+        error!("Coze API returned error ({:?}, logid {:?}): {}", coze_error.kind, coze_error.body.logid, error_body);
+        return Err(coze_error.into());
     }
 
     let coze_token_response = coze_response.json::<CozeTokenResponse>()
         .await
         .map_err(|e| {
             error!("Failed to parse Coze API response: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to parse Coze API response: {}", e)).into_response()
+            AppError::from(e)
         })?;
     debug!("Successfully received and parsed Coze API response");
     // --- End Exchange JWT ---
 
-    // Return the response from Coze API
-    info!("Token generation and exchange successful");
-    Ok(Json(coze_token_response))
+    Ok((status, coze_token_response))
 }
@@ -0,0 +1,425 @@
+use jsonwebtoken::{Algorithm, EncodingKey};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, RwLock};
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::auth::algorithm::{load_signing_key, normalize_pem};
+use crate::auth::region::{CozeRegion, RegionConfig, RegionSummary};
+
+// Deployment-wide settings for keys that do not specify their own
+#[derive(Debug, Clone)]
+pub struct KeyDefaults {
+    pub app_id: Option<String>, // COZE_APP_ID
+    pub region: CozeRegion,     // COZE_REGION (or COZE_API_URL)
+}
+
+// Where a signing key was loaded from; only file keys are retired by a file reload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Env,
+    File,
+    Admin,
+}
+
+// A private key as configured, before it is parsed (no Debug, it carries key material)
+#[derive(Clone, Default)]
+pub struct KeyMaterial {
+    pub pem: String,                // PKCS#1, SEC1 or PKCS#8; escaped "\n" is accepted
+    pub passphrase: Option<String>, // Required for encrypted PEMs
+    pub algorithm: Option<String>,  // RS256, PS256, ES256 or EdDSA; detected from the key when omitted
+}
+
+// Reads a PEM file, naming the path in the error
+pub fn read_key_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read private key file {}: {}", path, e))
+}
+
+// One private key registered with a Coze OAuth app, addressed by its key id
+#[derive(Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    pub app_id: String, // Coze OAuth app the key belongs to, used as the JWT issuer
+    pub region: CozeRegion, // Where the JWT is exchanged and which audience it names
+    pub algorithm: Algorithm, // Matches the key type: RS*/PS* for RSA, ES256 for P-256, EdDSA for Ed25519
+    pub encoding_key: EncodingKey,
+    pub public_jwk: Jwk, // Published at /.well-known/jwks.json
+    pub public_key_pem: String,
+    pub fingerprint: String, // SHA-256 of the PEM, used to detect replaced key material
+    pub source: KeySource,
+    pub added_at: i64,
+    pub retired_at: Option<i64>,
+}
+
+impl SigningKey {
+    // The algorithm defaults to the natural one for the detected key type
+    pub fn from_pem(kid: &str, material: &KeyMaterial, app_id: &str, region: CozeRegion, source: KeySource, now: i64) -> Result<Self, String> {
+        if app_id.is_empty() {
+            return Err(format!("No Coze app id configured for kid {}", kid));
+        }
+        let pem = normalize_pem(&material.pem);
+        let loaded = load_signing_key(kid, &pem, material.passphrase.as_deref(), material.algorithm.as_deref())
+            .map_err(|e| format!("Invalid private key for kid {}: {}", kid, e))?;
+        let fingerprint = openssl::sha::sha256(pem.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Ok(SigningKey {
+            kid: kid.to_string(),
+            app_id: app_id.to_string(),
+            region,
+            algorithm: loaded.algorithm,
+            encoding_key: loaded.encoding_key,
+            public_jwk: loaded.public_jwk,
+            public_key_pem: loaded.public_key_pem,
+            fingerprint,
+            source,
+            added_at: now,
+            retired_at: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotationKind {
+    Added,
+    Updated,
+    Activated,
+    Retired,
+    Expired,
+}
+
+// Emitted (and logged) on every change to the keyring
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyRotationEvent {
+    pub at: i64,
+    pub kind: KeyRotationKind,
+    pub kid: String,
+    pub source: KeySource,
+}
+
+// Public view of a key for the admin API; never includes key material
+#[derive(Debug, Clone, Serialize)]
+pub struct KeySummary {
+    pub kid: String,
+    pub app_id: String,
+    pub region: RegionSummary,
+    pub algorithm: Algorithm,
+    pub source: KeySource,
+    pub active: bool,
+    pub fingerprint: String,
+    pub public_key_pem: String,
+    pub added_at: i64,
+    pub retired_at: Option<i64>,
+    pub usable_until: Option<i64>,
+}
+
+#[derive(Clone, Default)]
+struct KeyringState {
+    keys: HashMap<String, SigningKey>,
+    active_kid: Option<String>,
+}
+
+// Number of rotation events kept for the admin API
+const EVENT_HISTORY_LIMIT: usize = 100;
+
+// All signing keys this deployment can use, keyed by kid. One kid is "active"
+// and used when a caller does not ask for a specific key; retired keys stay
+// usable for `grace_seconds` so in-flight callers can finish rotating.
+pub struct Keyring {
+    state: RwLock<KeyringState>,
+    pub grace_seconds: i64,
+    events: broadcast::Sender<KeyRotationEvent>,
+    history: Mutex<VecDeque<KeyRotationEvent>>,
+}
+
+impl Keyring {
+    pub fn new(grace_seconds: i64) -> Self {
+        let (events, _) = broadcast::channel(EVENT_HISTORY_LIMIT);
+        Keyring {
+            state: RwLock::new(KeyringState::default()),
+            grace_seconds,
+            events,
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn usable(&self, key: &SigningKey, now: i64) -> bool {
+        key.retired_at.is_none_or(|retired_at| now < retired_at + self.grace_seconds)
+    }
+
+    fn emit(&self, kind: KeyRotationKind, kid: &str, source: KeySource, now: i64) {
+        let event = KeyRotationEvent { at: now, kind, kid: kid.to_string(), source };
+        info!("Key rotation event: {:?} kid={} source={:?}", kind, kid, source);
+
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if history.len() == EVENT_HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyRotationEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn history(&self) -> Vec<KeyRotationEvent> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    pub fn active_kid(&self) -> Option<String> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).active_kid.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).keys.is_empty()
+    }
+
+    // Returns a key that may currently be used for signing
+    pub fn get(&self, kid: &str, now: i64) -> Option<SigningKey> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state.keys.get(kid).filter(|key| self.usable(key, now)).cloned()
+    }
+
+    // Picks the requested kid, or the active one when the caller did not ask for one
+    pub fn resolve(&self, kid: Option<&str>, now: i64) -> Result<SigningKey, String> {
+        let kid = match kid {
+            Some(kid) => kid.to_string(),
+            None => self.active_kid().ok_or("No active signing key; public_key is required")?,
+        };
+        self.get(&kid, now).ok_or_else(|| format!("Unknown public_key (kid): {}", kid))
+    }
+
+    // The change `key` makes to `state`, applied; None when it is already there unchanged
+    fn insert_into(state: &mut KeyringState, key: SigningKey) -> Option<KeyRotationKind> {
+        let kind = match state.keys.get(&key.kid) {
+            None => Some(KeyRotationKind::Added),
+            Some(existing) if existing.fingerprint != key.fingerprint
+                || existing.app_id != key.app_id
+                || existing.region != key.region
+                || existing.algorithm != key.algorithm
+                || existing.retired_at.is_some() => Some(KeyRotationKind::Updated),
+            Some(_) => None,
+        };
+        if kind.is_some() {
+            state.keys.insert(key.kid.clone(), key);
+        }
+        kind
+    }
+
+    // Returns whether the active kid changed
+    fn activate_in(&self, state: &mut KeyringState, kid: &str, now: i64) -> Result<bool, String> {
+        match state.keys.get(kid) {
+            Some(key) if self.usable(key, now) && key.retired_at.is_none() => {}
+            Some(_) => return Err(format!("Key {} is retired and cannot be activated", kid)),
+            None => return Err(format!("Unknown kid: {}", kid)),
+        }
+        if state.active_kid.as_deref() == Some(kid) {
+            return Ok(false);
+        }
+        state.active_kid = Some(kid.to_string());
+        Ok(true)
+    }
+
+    // Returns whether the key was newly retired
+    fn retire_in(state: &mut KeyringState, kid: &str, now: i64) -> Result<bool, String> {
+        if state.active_kid.as_deref() == Some(kid) {
+            return Err(format!("Key {} is active; activate another key before retiring it", kid));
+        }
+        let key = state.keys.get_mut(kid).ok_or_else(|| format!("Unknown kid: {}", kid))?;
+        if key.retired_at.is_some() {
+            return Ok(false);
+        }
+        key.retired_at = Some(now);
+        Ok(true)
+    }
+
+    // Adds a key, or replaces the material of an existing kid (un-retiring it)
+    pub fn insert(&self, key: SigningKey, now: i64) {
+        let (kid, source) = (key.kid.clone(), key.source);
+        let kind = Self::insert_into(&mut self.state.write().unwrap_or_else(|e| e.into_inner()), key);
+        if let Some(kind) = kind {
+            self.emit(kind, &kid, source, now);
+        }
+    }
+
+    pub fn activate(&self, kid: &str, source: KeySource, now: i64) -> Result<(), String> {
+        let changed = self.activate_in(&mut self.state.write().unwrap_or_else(|e| e.into_inner()), kid, now)?;
+        if changed {
+            self.emit(KeyRotationKind::Activated, kid, source, now);
+        }
+        Ok(())
+    }
+
+    // Starts the grace window for a key; the active key has to be replaced first
+    pub fn retire(&self, kid: &str, source: KeySource, now: i64) -> Result<(), String> {
+        let changed = Self::retire_in(&mut self.state.write().unwrap_or_else(|e| e.into_inner()), kid, now)?;
+        if changed {
+            self.emit(KeyRotationKind::Retired, kid, source, now);
+        }
+        Ok(())
+    }
+
+    // Applies a reloaded keyring file: new or changed keys are inserted, the
+    // file's active kid is applied and file keys that disappeared are retired.
+    // Everything is worked out on a copy first, so a file that cannot be applied
+    // as a whole (e.g. its active kid is unusable) leaves the keyring untouched.
+    pub fn sync_file_keys(&self, keys: Vec<SigningKey>, active_kid: Option<&str>, now: i64) -> Result<(), String> {
+        let mut events = Vec::new();
+        {
+            let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+            let mut updated = state.clone();
+
+            let listed: Vec<String> = keys.iter().map(|key| key.kid.clone()).collect();
+            for key in keys {
+                let (kid, source) = (key.kid.clone(), key.source);
+                if let Some(kind) = Self::insert_into(&mut updated, key) {
+                    events.push((kind, kid, source));
+                }
+            }
+
+            if let Some(kid) = active_kid {
+                if self.activate_in(&mut updated, kid, now)? {
+                    events.push((KeyRotationKind::Activated, kid.to_string(), KeySource::File));
+                }
+            }
+
+            let removed: Vec<String> = updated.keys.values()
+                .filter(|key| key.source == KeySource::File && key.retired_at.is_none() && !listed.contains(&key.kid))
+                .map(|key| key.kid.clone())
+                .collect();
+            for kid in removed {
+                if Self::retire_in(&mut updated, &kid, now)? {
+                    events.push((KeyRotationKind::Retired, kid, KeySource::File));
+                }
+            }
+
+            *state = updated;
+        }
+        for (kind, kid, source) in events {
+            self.emit(kind, &kid, source, now);
+        }
+        Ok(())
+    }
+
+    // Drops retired keys whose grace window has ended
+    pub fn prune(&self, now: i64) {
+        let expired: Vec<(String, KeySource)> = {
+            let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+            let expired: Vec<(String, KeySource)> = state.keys.values()
+                .filter(|key| !self.usable(key, now))
+                .map(|key| (key.kid.clone(), key.source))
+                .collect();
+            for (kid, _) in &expired {
+                state.keys.remove(kid);
+            }
+            expired
+        };
+        for (kid, source) in expired {
+            self.emit(KeyRotationKind::Expired, &kid, source, now);
+        }
+    }
+
+    // Public keys of every usable key, including retired ones still in their
+    // grace window, so tokens they signed can still be verified
+    pub fn jwks(&self, now: i64) -> JwkSet {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        let mut keys: Vec<&SigningKey> = state.keys.values().filter(|key| self.usable(key, now)).collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        JwkSet {
+            keys: keys.into_iter().map(|key| key.public_jwk.clone()).collect(),
+        }
+    }
+
+    pub fn summaries(&self, now: i64) -> Vec<KeySummary> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        let mut summaries: Vec<KeySummary> = state.keys.values()
+            .filter(|key| self.usable(key, now))
+            .map(|key| KeySummary {
+                kid: key.kid.clone(),
+                app_id: key.app_id.clone(),
+                region: RegionSummary::from(&key.region),
+                algorithm: key.algorithm,
+                source: key.source,
+                active: state.active_kid.as_deref() == Some(key.kid.as_str()),
+                fingerprint: key.fingerprint.clone(),
+                public_key_pem: key.public_key_pem.clone(),
+                added_at: key.added_at,
+                retired_at: key.retired_at,
+                usable_until: key.retired_at.map(|retired_at| retired_at + self.grace_seconds),
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.kid.cmp(&b.kid));
+        summaries
+    }
+}
+
+// Layout of the JSON file referenced by JWT_KEYRING_FILE
+#[derive(Debug, Deserialize)]
+pub struct KeyringFile {
+    #[serde(default)]
+    pub active_kid: Option<String>,
+    pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    #[serde(default)]
+    pub private_key: Option<String>, // PEM, newlines escaped as \n inside the JSON string
+    #[serde(default)]
+    pub private_key_file: Option<String>, // PEM file, relative to the keyring file; alternative to private_key
+    #[serde(default)]
+    pub passphrase_env: Option<String>, // Name of the env var holding the passphrase of an encrypted PEM
+    #[serde(default)]
+    pub algorithm: Option<String>, // RS256, PS256, ES256 or EdDSA; detected from the key when omitted
+    #[serde(default)]
+    pub app_id: Option<String>, // Falls back to COZE_APP_ID
+    #[serde(flatten)]
+    pub region: RegionConfig, // Falls back to COZE_REGION
+}
+
+impl KeyConfig {
+    // Resolves the inline PEM or key file, and the passphrase from the environment
+    fn material(&self, base_dir: &std::path::Path) -> Result<KeyMaterial, String> {
+        let pem = match (&self.private_key, &self.private_key_file) {
+            (Some(pem), None) => pem.clone(),
+            (None, Some(file)) => read_key_file(&base_dir.join(file).to_string_lossy())?,
+            (Some(_), Some(_)) => return Err("set either private_key or private_key_file, not both".to_string()),
+            (None, None) => return Err("private_key or private_key_file is required".to_string()),
+        };
+        let passphrase = match self.passphrase_env.as_deref() {
+            Some(name) => Some(std::env::var(name).map_err(|_| format!("passphrase_env {} is not set", name))?),
+            None => None,
+        };
+        Ok(KeyMaterial { pem, passphrase, algorithm: self.algorithm.clone() })
+    }
+}
+
+// Reads the keyring file into signing keys plus the kid it marks as active
+pub fn read_keyring_file(path: &str, defaults: &KeyDefaults, now: i64) -> Result<(Vec<SigningKey>, Option<String>), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read keyring file {}: {}", path, e))?;
+    let file: KeyringFile = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse keyring file {}: {}", path, e))?;
+    let base_dir = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new("."));
+
+    let keys = file.keys.iter()
+        .map(|key| {
+            let app_id = key.app_id.as_deref().or(defaults.app_id.as_deref()).unwrap_or_default();
+            let region = key.region.resolve(&defaults.region)
+                .map_err(|e| format!("Invalid region for kid {}: {}", key.kid, e))?;
+            let material = key.material(base_dir)
+                .map_err(|e| format!("Invalid private key for kid {}: {}", key.kid, e))?;
+            SigningKey::from_pem(&key.kid, &material, app_id, region, KeySource::File, now)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((keys, file.active_kid))
+}
@@ -1,2 +1,9 @@
 pub mod model;
 pub mod handler;
+pub mod cache;
+pub mod keyring;
+pub mod clients;
+pub mod region;
+pub mod algorithm;
+pub mod upstream;
+pub mod clock;
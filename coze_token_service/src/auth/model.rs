@@ -1,6 +1,16 @@
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::EncodingKey;
+use serde_json::{Map, Value};
 use reqwest::Client;
+use std::sync::Arc;
+
+use crate::auth::cache::TokenCache;
+use crate::auth::clients::{ApiClient, ClientRegistry};
+use crate::auth::clock::Clock;
+use crate::auth::keyring::{KeyDefaults, Keyring, SigningKey};
+use crate::format::allowlist::ResendRoutes;
+use crate::feishu::cache::FeishuTokenCache;
+use crate::format::credentials::Credentials;
+use crate::services::refresh::TokenRefresher;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExampleModel {
@@ -9,21 +19,100 @@ pub struct ExampleModel {
     pub description: String,
 }
 
-// AppConfig holds JWT key, expected API key, and Coze API URL
+// AppConfig holds the signing keys, API clients, and Coze settings
 #[derive(Clone)]
 pub struct AppConfig {
-    pub encoding_key: EncodingKey,
-    pub expected_coze_api_key: String,
-    pub coze_api_url: String, // Added Coze API URL
+    pub keyring: Arc<Keyring>, // Signing keys by kid, rotated at runtime
+    pub keyring_file: Option<String>, // JWT_KEYRING_FILE, watched for changes
+    pub keyring_watch_interval_seconds: u64,
+    pub key_defaults: KeyDefaults, // App id and Coze region for keys that do not set their own
+    pub duration_bounds: DurationBounds, // Allowed range for TokenRequest.duration_seconds
+    pub clients: Arc<ClientRegistry>, // Per-client API keys and their permissions
     pub http_client: Client, // Added HTTP client
+    pub upstream_timeout_seconds: u64, // Also applied to the per-request /resend clients
+    pub token_cache: Arc<TokenCache>, // Coze access tokens shared across requests
+    pub token_refresher: Arc<TokenRefresher>, // Key ids kept warm by the background refresher
+    pub admin_api_key: Option<String>, // Enables the /admin endpoints when set
+    pub debug_endpoints_enabled: bool, // Enables /token/jwt and /token/verify
+    pub clock: Arc<dyn Clock>, // Time source for JWTs, the cache and key grace windows
+    pub iat_leeway_seconds: i64, // How far iat is back-dated
+    pub clock_skew_warn_seconds: i64, // Warn when Coze's Date header is further off than this
+    pub resend_routes: Arc<ResendRoutes>, // Destinations /resend may forward to
+    pub resend_routes_watch_interval_seconds: u64,
+    pub credentials: Arc<Credentials>, // Named secrets /resend attaches to outbound requests
+    pub feishu_base_url: String, // Feishu (or Lark) open platform, for app access tokens
+    pub feishu_token_cache: Arc<FeishuTokenCache>,
+}
+
+// Coze refuses access tokens valid for longer than 24 hours
+pub const COZE_MAX_DURATION_SECONDS: u64 = 86400;
+
+// Deployment-wide limits on requested token durations
+#[derive(Debug, Clone, Copy)]
+pub struct DurationBounds {
+    pub min_seconds: u64,
+    pub max_seconds: u64,
+}
+
+impl DurationBounds {
+    // A client's own maximum replaces the global one, but never exceeds Coze's cap
+    pub fn for_client(&self, client_max: Option<u64>) -> DurationBounds {
+        let max_seconds = client_max.unwrap_or(self.max_seconds).min(COZE_MAX_DURATION_SECONDS);
+        DurationBounds {
+            min_seconds: self.min_seconds.min(max_seconds),
+            max_seconds,
+        }
+    }
+
+    pub fn contains(&self, duration_seconds: u64) -> bool {
+        (self.min_seconds..=self.max_seconds).contains(&duration_seconds)
+    }
 }
 
-// Request body for our service
-#[derive(Debug, Deserialize)]
+// Request body for our service (no Debug, it carries the caller's API key)
+#[derive(Deserialize)]
 pub struct TokenRequest {
-    pub public_key: String,
+    pub public_key: Option<String>, // Defaults to the active key id
     pub coze_api_key: String,
-    pub duration_seconds: Option<u64>,
+    pub duration_seconds: Option<u64>, // Defaults to the largest allowed duration
+    pub session_name: Option<String>, // Isolates Coze conversations per end user
+    #[serde(default)]
+    pub extra_claims: Map<String, Value>, // Only names listed in the client's allowed_extra_claims
+    pub scope: Option<TokenScope>, // Restricts the token; defaults to the client's allowlist
+}
+
+// Permissions (and bot ids) a token is restricted to, as callers request them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenScope {
+    #[serde(default)]
+    pub permissions: Vec<String>, // e.g. "Connector.botChat"
+    #[serde(default)]
+    pub bot_ids: Vec<String>, // Empty leaves bots unrestricted
+}
+
+// Claims the service always sets itself; callers can never override them
+pub const RESERVED_CLAIMS: &[&str] = &["iat", "exp", "nbf", "jti", "aud", "iss", "sub", "session_name"];
+
+// Longest session_name accepted from callers
+pub const MAX_SESSION_NAME_LENGTH: usize = 128;
+
+// Caller-specific claims embedded in the JWT. Tokens are cached per
+// session, so two end users never share a Coze access token.
+#[derive(Debug, Clone, Default)]
+pub struct SessionClaims {
+    pub session_name: Option<String>,
+    pub extra: Map<String, Value>,
+}
+
+// A /token request that passed every policy check, ready to be signed
+pub struct AuthorizedTokenRequest {
+    pub client: ApiClient,
+    pub signing_key: SigningKey,
+    pub duration: u64,
+    pub max_duration: u64, // The caller's limit, which cached tokens must respect as well
+    pub session: SessionClaims,
+    pub scope: Option<TokenScope>,
+    pub now: i64,
 }
 
 // Request body for Coze API
@@ -31,16 +120,95 @@ pub struct TokenRequest {
 pub struct CozeTokenRequest {
     pub duration_seconds: u64,
     pub grant_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<CozeScope>,
+}
+
+// Coze's representation of a restricted token scope
+#[derive(Debug, Serialize)]
+pub struct CozeScope {
+    pub account_permission: CozeAccountPermission,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribute_constraint: Option<CozeAttributeConstraint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CozeAccountPermission {
+    pub permission_list: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CozeAttributeConstraint {
+    pub connector_bot_chat_attribute: CozeBotChatAttribute,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CozeBotChatAttribute {
+    pub bot_id_list: Vec<String>,
+}
+
+impl From<&TokenScope> for CozeScope {
+    fn from(scope: &TokenScope) -> Self {
+        CozeScope {
+            account_permission: CozeAccountPermission {
+                permission_list: scope.permissions.clone(),
+            },
+            attribute_constraint: (!scope.bot_ids.is_empty()).then(|| CozeAttributeConstraint {
+                connector_bot_chat_attribute: CozeBotChatAttribute {
+                    bot_id_list: scope.bot_ids.clone(),
+                },
+            }),
+        }
+    }
 }
 
 // Response body from Coze API (and our service)
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CozeTokenResponse {
     pub access_token: String,
     pub expires_in: i64, // Coze API might return seconds, adjust if needed
     pub token_type: String,
 }
 
+// Response of POST /token/jwt: what /token would have sent to Coze
+#[derive(Debug, Serialize)]
+pub struct JwtDebugResponse {
+    pub jwt: String,
+    pub header: jsonwebtoken::Header,
+    pub claims: Claims,
+    pub token_url: String,
+    pub exchange_request: CozeTokenRequest,
+}
+
+// Request body of POST /token/verify (no Debug, it carries the caller's API key)
+#[derive(Deserialize)]
+pub struct VerifyJwtRequest {
+    pub coze_api_key: String,
+    pub jwt: String,
+}
+
+// Response of POST /token/verify; an invalid JWT is reported, not treated as a failed request
+#[derive(Debug, Serialize)]
+pub struct VerifyJwtResponse {
+    pub valid: bool,
+    pub kid: Option<String>,
+    pub header: Option<jsonwebtoken::Header>,
+    pub claims: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl VerifyJwtResponse {
+    pub fn invalid(header: Option<jsonwebtoken::Header>, error: String) -> Self {
+        VerifyJwtResponse {
+            valid: false,
+            kid: header.as_ref().and_then(|header| header.kid.clone()),
+            header,
+            claims: None,
+            error: Some(error),
+        }
+    }
+}
+
 // Claims structure for the JWT
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -49,4 +217,8 @@ pub struct Claims {
     pub jti: String,
     pub aud: String,
     pub iss: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_name: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>, // e.g. Coze's session_context
 }
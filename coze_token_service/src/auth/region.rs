@@ -0,0 +1,97 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+// Which Coze deployment a key belongs to. The token endpoint and the JWT
+// audience are always derived together so they cannot drift apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CozeRegion {
+    Cn,  // coze.cn (mainland China)
+    Com, // coze.com (international)
+    Custom { token_url: String, audience: String },
+}
+
+impl CozeRegion {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CozeRegion::Cn => "cn",
+            CozeRegion::Com => "com",
+            CozeRegion::Custom { .. } => "custom",
+        }
+    }
+
+    pub fn token_url(&self) -> String {
+        match self {
+            CozeRegion::Cn => "https://api.coze.cn/api/permission/oauth2/token".to_string(),
+            CozeRegion::Com => "https://api.coze.com/api/permission/oauth2/token".to_string(),
+            CozeRegion::Custom { token_url, .. } => token_url.clone(),
+        }
+    }
+
+    pub fn audience(&self) -> String {
+        match self {
+            CozeRegion::Cn => "api.coze.cn".to_string(),
+            CozeRegion::Com => "api.coze.com".to_string(),
+            CozeRegion::Custom { audience, .. } => audience.clone(),
+        }
+    }
+
+    // `api_url` is required for "custom"; its audience defaults to the URL's host
+    pub fn parse(region: &str, api_url: Option<&str>, audience: Option<&str>) -> Result<Self, String> {
+        match region.to_ascii_lowercase().as_str() {
+            "cn" => Ok(CozeRegion::Cn),
+            "com" => Ok(CozeRegion::Com),
+            "custom" => {
+                let token_url = api_url.ok_or("A custom Coze region requires an api_url")?;
+                let audience = match audience {
+                    Some(audience) => audience.to_string(),
+                    None => Url::parse(token_url)
+                        .map_err(|e| format!("Invalid Coze api_url {}: {}", token_url, e))?
+                        .host_str()
+                        .ok_or_else(|| format!("Coze api_url {} has no host", token_url))?
+                        .to_string(),
+                };
+                Ok(CozeRegion::Custom { token_url: token_url.to_string(), audience })
+            }
+            other => Err(format!("Unknown Coze region {} (expected cn, com or custom)", other)),
+        }
+    }
+}
+
+// Region settings as they appear in the keyring file and admin requests
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RegionConfig {
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub audience: Option<String>,
+}
+
+impl RegionConfig {
+    // Falls back to the deployment-wide default when no region is given
+    pub fn resolve(&self, default: &CozeRegion) -> Result<CozeRegion, String> {
+        match self.region.as_deref() {
+            Some(region) => CozeRegion::parse(region, self.api_url.as_deref(), self.audience.as_deref()),
+            None => Ok(default.clone()),
+        }
+    }
+}
+
+// How a key's region is shown by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionSummary {
+    pub region: &'static str,
+    pub token_url: String,
+    pub audience: String,
+}
+
+impl From<&CozeRegion> for RegionSummary {
+    fn from(region: &CozeRegion) -> Self {
+        RegionSummary {
+            region: region.name(),
+            token_url: region.token_url(),
+            audience: region.audience(),
+        }
+    }
+}
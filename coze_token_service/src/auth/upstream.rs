@@ -0,0 +1,125 @@
+use axum::http::{HeaderValue, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::error::{AppError, UpstreamError};
+
+// Error body of the Coze OAuth token endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CozeErrorResponse {
+    #[serde(default)]
+    pub error_code: Option<String>,
+    #[serde(default)]
+    pub error_message: Option<String>,
+    #[serde(default)]
+    pub logid: Option<String>, // Also sent as the x-tt-logid header; quote it to Coze support
+}
+
+// How a failed exchange is reported to our callers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CozeErrorKind {
+    InvalidKey,  // Coze does not accept our key, kid or app id
+    ClockSkew,   // The JWT's iat/exp is off from Coze's clock
+    RateLimited, // Coze asked us to slow down
+    Other,
+}
+
+impl CozeErrorKind {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            // Our key or app id is misconfigured; the caller's credentials are fine
+            CozeErrorKind::InvalidKey => StatusCode::BAD_GATEWAY,
+            CozeErrorKind::ClockSkew => StatusCode::SERVICE_UNAVAILABLE,
+            CozeErrorKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            CozeErrorKind::Other => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            CozeErrorKind::InvalidKey => "coze_invalid_key",
+            CozeErrorKind::ClockSkew => "coze_clock_skew",
+            CozeErrorKind::RateLimited => "coze_rate_limited",
+            CozeErrorKind::Other => "coze_error",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            CozeErrorKind::InvalidKey => "Coze rejected the signing key or app id",
+            CozeErrorKind::ClockSkew => "Coze rejected the JWT's timestamps; check the server clock",
+            CozeErrorKind::RateLimited => "Coze rate limit exceeded",
+            CozeErrorKind::Other => "Coze API returned an error",
+        }
+    }
+}
+
+// Words in error_message that point at the JWT's timestamps
+const CLOCK_SKEW_WORDS: &[&str] = &["iat", "exp", "expired", "nbf", "clock", "skew", "future"];
+
+const INVALID_KEY_CODES: &[&str] = &["invalid_client", "unauthorized_client", "invalid_key", "invalid_public_key"];
+
+pub fn classify(status: StatusCode, body: &CozeErrorResponse) -> CozeErrorKind {
+    let code = body.error_code.as_deref().unwrap_or_default().to_ascii_lowercase();
+    let message = body.error_message.as_deref().unwrap_or_default().to_ascii_lowercase();
+    let mentions_clock = message
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| CLOCK_SKEW_WORDS.contains(&word));
+
+    if status == StatusCode::TOO_MANY_REQUESTS || code.contains("rate_limit") {
+        CozeErrorKind::RateLimited
+    } else if mentions_clock {
+        CozeErrorKind::ClockSkew
+    } else if status == StatusCode::UNAUTHORIZED || INVALID_KEY_CODES.contains(&code.as_str()) {
+        CozeErrorKind::InvalidKey
+    } else {
+        CozeErrorKind::Other
+    }
+}
+
+// A failed token exchange, reported as an upstream AppError whose details are
+// {"upstream_status", "coze": {error_code, error_message, logid}}
+#[derive(Debug, Clone)]
+pub struct CozeError {
+    pub kind: CozeErrorKind,
+    pub upstream_status: StatusCode,
+    pub body: CozeErrorResponse,
+    pub retry_after: Option<HeaderValue>, // Passed on for rate limiting
+}
+
+impl CozeError {
+    // Parses the error body; a non-JSON body is kept as the error message
+    pub fn from_upstream(upstream_status: StatusCode, logid: Option<String>, retry_after: Option<HeaderValue>, text: &str) -> Self {
+        let mut body = serde_json::from_str::<CozeErrorResponse>(text).unwrap_or_else(|_| CozeErrorResponse {
+            error_message: Some(text.to_string()).filter(|text| !text.is_empty()),
+            ..Default::default()
+        });
+        if body.logid.is_none() {
+            body.logid = logid;
+        }
+        CozeError {
+            kind: classify(upstream_status, &body),
+            upstream_status,
+            body,
+            retry_after,
+        }
+    }
+}
+
+impl From<CozeError> for AppError {
+    fn from(e: CozeError) -> Self {
+        AppError::Upstream(Box::new(UpstreamError {
+            status: e.kind.status(),
+            code: e.kind.code(),
+            title: e.kind.title(),
+            // Coze's own message is the most specific explanation we have
+            message: e.body.error_message.clone().unwrap_or_else(|| e.kind.title().to_string()),
+            details: Some(json!({
+                "upstream_status": e.upstream_status.as_u16(),
+                "coze": e.body,
+            })),
+            // Only passed on when Coze is rate limiting us
+            retry_after: e.retry_after.filter(|_| e.kind == CozeErrorKind::RateLimited),
+        }))
+    }
+}
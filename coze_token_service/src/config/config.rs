@@ -1,24 +1,236 @@
-use jsonwebtoken::EncodingKey;
 use reqwest::Client;
-use std::{env, sync::Arc};
+use std::{env, fmt, str::FromStr, sync::Arc, time::Duration};
 use dotenvy::dotenv;
-use crate::auth::model::AppConfig;
+use tracing::warn;
+use crate::auth::{cache::TokenCache, model::{AppConfig, DurationBounds, COZE_MAX_DURATION_SECONDS}};
+use crate::auth::clients::ClientRegistry;
+use crate::auth::clock::{Clock, SystemClock};
+use crate::auth::keyring::{read_key_file, read_keyring_file, KeyDefaults, KeyMaterial, KeySource, Keyring, SigningKey};
+use crate::auth::region::CozeRegion;
+use crate::format::allowlist::ResendRoutes;
+use crate::feishu::cache::FeishuTokenCache;
+use crate::format::credentials::Credentials;
+use crate::services::refresh::{parse_refresh_targets, TokenRefresher};
 
-pub fn load_config() -> Arc<AppConfig> {
+// Why the service refused to start; logged once before the process exits
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(&'static str), // A required setting is unset
+    Invalid { setting: &'static str, reason: String }, // A setting is present but unusable
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(setting) => write!(f, "{} must be set", setting),
+            ConfigError::Invalid { setting, reason } => write!(f, "{}: {}", setting, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(setting: &'static str) -> impl FnOnce(String) -> ConfigError {
+    move |reason| ConfigError::Invalid { setting, reason }
+}
+
+// Reads an optional string setting, treating an empty value as unset
+fn env_opt(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+// Reads an optional setting, falling back to `default` when unset or empty. A
+// value that does not parse stops the service instead of being silently replaced.
+fn env_or<T: FromStr>(name: &'static str, default: T) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    match env_opt(name) {
+        None => Ok(default),
+        Some(value) => value.parse::<T>()
+            .map_err(|e| ConfigError::Invalid { setting: name, reason: format!("{:?} is not valid: {}", value, e) }),
+    }
+}
+
+// COZE_REGION picks cn (default) or com; "custom" (or a bare COZE_API_URL, as
+// older deployments configure it) exchanges at COZE_API_URL with COZE_AUDIENCE
+fn load_default_region() -> Result<CozeRegion, ConfigError> {
+    let api_url = env_opt("COZE_API_URL");
+    let audience = env_opt("COZE_AUDIENCE");
+    let region = env_opt("COZE_REGION")
+        .unwrap_or_else(|| if api_url.is_some() { "custom".to_string() } else { "cn".to_string() });
+
+    let region = CozeRegion::parse(&region, api_url.as_deref(), audience.as_deref()).map_err(invalid("COZE_REGION"))?;
+    if api_url.is_some() && region.name() != "custom" {
+        warn!("COZE_API_URL is ignored because COZE_REGION={}", region.name());
+    }
+    Ok(region)
+}
+
+// The single key configured directly in the environment, as an inline PEM or a file
+fn load_env_key_material() -> Result<Option<(&'static str, KeyMaterial)>, ConfigError> {
+    let (setting, pem) = match (env_opt("JWT_PRIVATE_KEY"), env_opt("JWT_PRIVATE_KEY_FILE")) {
+        (None, None) => return Ok(None),
+        (Some(pem), None) => ("JWT_PRIVATE_KEY", pem),
+        (None, Some(path)) => ("JWT_PRIVATE_KEY_FILE", read_key_file(&path).map_err(invalid("JWT_PRIVATE_KEY_FILE"))?),
+        (Some(_), Some(_)) => return Err(ConfigError::Invalid {
+            setting: "JWT_PRIVATE_KEY_FILE",
+            reason: "set either JWT_PRIVATE_KEY or JWT_PRIVATE_KEY_FILE, not both".to_string(),
+        }),
+    };
+    Ok(Some((setting, KeyMaterial {
+        pem,
+        passphrase: env_opt("JWT_PRIVATE_KEY_PASSPHRASE"),
+        algorithm: env_opt("JWT_ALGORITHM"), // The key type decides the default
+    })))
+}
+
+fn load_keyring(defaults: &KeyDefaults, now: i64) -> Result<Keyring, ConfigError> {
+    // Retired keys remain usable for this long after being removed
+    let keyring = Keyring::new(env_or("JWT_KEY_GRACE_SECONDS", 86400)?);
+
+    let env_kid = env_opt("JWT_KEY_ID");
+    let env_material = load_env_key_material()?;
+    if let Some((setting, material)) = &env_material {
+        let kid = env_kid.as_deref().ok_or(ConfigError::Missing("JWT_KEY_ID"))?;
+        let app_id = defaults.app_id.as_deref().ok_or(ConfigError::Missing("COZE_APP_ID"))?;
+        let key = SigningKey::from_pem(kid, material, app_id, defaults.region.clone(), KeySource::Env, now)
+            .map_err(invalid(setting))?;
+        keyring.insert(key, now);
+    }
+
+    // Keys from the keyring file, one entry per Coze OAuth app / key id
+    let mut file_active_kid = None;
+    if let Some(path) = env_opt("JWT_KEYRING_FILE") {
+        let (keys, active_kid) = read_keyring_file(&path, defaults, now).map_err(invalid("JWT_KEYRING_FILE"))?;
+        for key in keys {
+            keyring.insert(key, now);
+        }
+        file_active_kid = active_kid;
+    }
+
+    if keyring.is_empty() {
+        return Err(ConfigError::Missing("JWT_PRIVATE_KEY, JWT_PRIVATE_KEY_FILE or JWT_KEYRING_FILE"));
+    }
+
+    // The keyring file's active_kid wins over JWT_KEY_ID; a lone key is active by default.
+    // A failure is reported against the setting that chose the kid.
+    let active_kid = file_active_kid.map(|kid| ("JWT_KEYRING_FILE", kid))
+        .or(env_kid.filter(|_| env_material.is_some()).map(|kid| ("JWT_KEY_ID", kid)))
+        .or_else(|| {
+            let summaries = keyring.summaries(now);
+            (summaries.len() == 1).then(|| ("JWT_KEYRING_FILE", summaries[0].kid.clone()))
+        });
+    if let Some((setting, kid)) = active_kid {
+        keyring.activate(&kid, KeySource::Env, now).map_err(invalid(setting))?;
+    }
+    Ok(keyring)
+}
+
+pub fn load_config() -> Result<Arc<AppConfig>, ConfigError> {
     dotenv().ok();
 
-    let private_key_pem = env::var("JWT_PRIVATE_KEY").expect("JWT_PRIVATE_KEY must be set");
-    let encoding_key = EncodingKey::from_rsa_pem(private_key_pem.as_bytes()).expect("Failed to create encoding key from PEM");
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let now = clock.now();
+    let key_defaults = KeyDefaults {
+        // Coze OAuth app id used as the JWT issuer for keys that do not name their own
+        app_id: env_opt("COZE_APP_ID"),
+        region: load_default_region()?,
+    };
+    let keyring = Arc::new(load_keyring(&key_defaults, now)?);
+    let keyring_file = env_opt("JWT_KEYRING_FILE");
+
+    // Per-client API keys, optionally persisted to CLIENTS_FILE
+    let clients = ClientRegistry::load(env_opt("CLIENTS_FILE")).map_err(invalid("CLIENTS_FILE"))?;
+    let expected_coze_api_key = env_opt("EXPECTED_COZE_API_KEY");
+    if let Some(api_key) = expected_coze_api_key.as_deref() {
+        clients.register_builtin(api_key, now);
+    }
+    let clients = Arc::new(clients);
+
+    // Applies to calls to Coze and /resend destinations; exceeding it answers 504
+    let upstream_timeout_seconds = env_or("UPSTREAM_TIMEOUT_SECONDS", 30)?;
+    let http_client = Client::builder()
+        .timeout(Duration::from_secs(upstream_timeout_seconds))
+        .build()
+        .map_err(|e| ConfigError::Invalid { setting: "UPSTREAM_TIMEOUT_SECONDS", reason: e.to_string() })?;
+
+    // Cached tokens are reused until this many seconds before they expire
+    let refresh_margin = env_or("TOKEN_CACHE_REFRESH_MARGIN_SECONDS", 300)?;
+    // Requested durations are rounded up to buckets of this size when caching
+    let bucket_seconds = env_or("TOKEN_CACHE_BUCKET_SECONDS", 3600)?;
+    // Tokens kept at most; expired ones are dropped first, then those expiring soonest
+    let max_entries = env_or("TOKEN_CACHE_MAX_ENTRIES", 10000)?;
+    let token_cache = Arc::new(TokenCache::new(refresh_margin, bucket_seconds, max_entries));
+
+    // Bounds for requested token durations; Coze itself caps tokens at 24 hours
+    let duration_bounds = DurationBounds {
+        min_seconds: env_or("TOKEN_MIN_DURATION_SECONDS", 60)?,
+        max_seconds: env_or("TOKEN_MAX_DURATION_SECONDS", COZE_MAX_DURATION_SECONDS)?,
+    };
+    if duration_bounds.min_seconds < 1 {
+        return Err(ConfigError::Invalid { setting: "TOKEN_MIN_DURATION_SECONDS", reason: "must be at least 1".to_string() });
+    }
+    if duration_bounds.max_seconds > COZE_MAX_DURATION_SECONDS {
+        return Err(ConfigError::Invalid { setting: "TOKEN_MAX_DURATION_SECONDS", reason: format!("must not exceed {}", COZE_MAX_DURATION_SECONDS) });
+    }
+    if duration_bounds.min_seconds > duration_bounds.max_seconds {
+        return Err(ConfigError::Invalid { setting: "TOKEN_MIN_DURATION_SECONDS", reason: "must not exceed TOKEN_MAX_DURATION_SECONDS".to_string() });
+    }
+
+    // Key ids refreshed in the background, e.g. "kid_a,kid_b:3600"
+    let refresh_targets = parse_refresh_targets(&env::var("TOKEN_REFRESH_KEYS").unwrap_or_default(), duration_bounds.max_seconds);
+    for target in &refresh_targets {
+        if keyring.get(&target.kid, now).is_none() {
+            return Err(ConfigError::Invalid { setting: "TOKEN_REFRESH_KEYS", reason: format!("unknown kid {}", target.kid) });
+        }
+        if !duration_bounds.contains(target.duration_seconds) {
+            return Err(ConfigError::Invalid { setting: "TOKEN_REFRESH_KEYS", reason: format!("duration for kid {} is out of bounds", target.kid) });
+        }
+    }
+    let token_refresher = Arc::new(TokenRefresher::new(
+        refresh_targets,
+        env_or("TOKEN_REFRESH_LEAD_SECONDS", 300)?,
+        env_or("TOKEN_REFRESH_BACKOFF_BASE_SECONDS", 5)?,
+        env_or("TOKEN_REFRESH_BACKOFF_MAX_SECONDS", 600)?,
+    ));
+
+    // Destinations /resend may forward to; only Feishu bitable batch_create without a file
+    let resend_routes = Arc::new(ResendRoutes::load(env_opt("RESEND_ROUTES_FILE")).map_err(invalid("RESEND_ROUTES_FILE"))?);
+
+    // Secrets /resend payloads refer to by name instead of carrying them
+    let credentials = Arc::new(Credentials::load(env_opt("CREDENTIALS_FILE")).map_err(invalid("CREDENTIALS_FILE"))?);
 
-    let expected_coze_api_key = env::var("EXPECTED_COZE_API_KEY").expect("EXPECTED_COZE_API_KEY must be set");
-    let coze_api_url = env::var("COZE_API_URL").expect("COZE_API_URL must be set");
+    // Feishu app access tokens for credentials backed by a feishu_app; Lark is https://open.larksuite.com
+    let feishu_base_url = env_opt("FEISHU_BASE_URL").unwrap_or_else(|| "https://open.feishu.cn".to_string());
+    let feishu_base_url = feishu_base_url.trim_end_matches('/').to_string();
+    let feishu_token_cache = Arc::new(FeishuTokenCache::new(env_or("FEISHU_TOKEN_REFRESH_MARGIN_SECONDS", 300)?));
 
-    let http_client = Client::new();
+    let admin_api_key = env_opt("ADMIN_API_KEY");
+    // Off by default: a minted JWT can be exchanged with Coze without our scope checks
+    let debug_endpoints_enabled = env_or("DEBUG_TOKEN_ENDPOINTS", false)?;
 
-    Arc::new(AppConfig {
-        encoding_key,
-        expected_coze_api_key,
-        coze_api_url,
+    Ok(Arc::new(AppConfig {
+        keyring,
+        keyring_file,
+        keyring_watch_interval_seconds: env_or("JWT_KEYRING_WATCH_INTERVAL_SECONDS", 30)?,
+        key_defaults,
+        duration_bounds,
+        clients,
         http_client,
-    })
+        upstream_timeout_seconds,
+        token_cache,
+        token_refresher,
+        admin_api_key,
+        debug_endpoints_enabled,
+        clock,
+        // Back-dating iat tolerates a local clock running ahead of Coze's
+        iat_leeway_seconds: env_or("JWT_IAT_LEEWAY_SECONDS", 30)?,
+        clock_skew_warn_seconds: env_or("CLOCK_SKEW_WARN_SECONDS", 10)?,
+        resend_routes,
+        resend_routes_watch_interval_seconds: env_or("RESEND_ROUTES_WATCH_INTERVAL_SECONDS", 30)?,
+        credentials,
+        feishu_base_url,
+        feishu_token_cache,
+    }))
 }
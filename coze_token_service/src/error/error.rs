@@ -1,14 +1,185 @@
-use axum::response::{IntoResponse, Response};
-use axum::http::StatusCode;
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::fmt;
 
+use crate::auth::clients::ClientAuthError;
+use crate::config::config::ConfigError;
+use crate::error::http::ProblemDetails;
+
+// Every error a handler can return, sent as an RFC 7807 problem document
+// (see error::http) with a status decided by the variant
 #[derive(Debug)]
 pub enum AppError {
-    // 定义应用错误类型
+    Validation { message: String, details: Option<Value> }, // The request itself is malformed or out of bounds
+    Unauthorized(String), // Missing or unknown credentials
+    Forbidden(String),    // Valid credentials, but not for this route, key, claim or destination
+    NotFound(String),
+    Upstream(Box<UpstreamError>), // The service we called failed or rejected the call
+    UpstreamTimeout(String),
+    Config(String), // The service is misconfigured
+    Internal(String),
+}
+
+// A failed call to Coze or a /resend destination; boxed to keep AppError small
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub status: StatusCode, // What we answer with, not what the upstream sent
+    pub code: &'static str,
+    pub title: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+    pub retry_after: Option<HeaderValue>,
 }
 
+impl AppError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation { message: message.into(), details: None }
+    }
+
+    pub fn upstream(code: &'static str, title: &'static str, message: impl Into<String>) -> Self {
+        AppError::Upstream(Box::new(UpstreamError {
+            status: StatusCode::BAD_GATEWAY,
+            code,
+            title,
+            message: message.into(),
+            details: None,
+            retry_after: None,
+        }))
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Validation { .. } => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Upstream(upstream) => upstream.status,
+            AppError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Config(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation { .. } => "validation_error",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Upstream(upstream) => upstream.code,
+            AppError::UpstreamTimeout(_) => "upstream_timeout",
+            AppError::Config(_) => "config_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    // The same for every error with this code
+    pub fn title(&self) -> &'static str {
+        match self {
+            AppError::Validation { .. } => "Invalid request",
+            AppError::Unauthorized(_) => "Unauthorized",
+            AppError::Forbidden(_) => "Forbidden",
+            AppError::NotFound(_) => "Not found",
+            AppError::Upstream(upstream) => upstream.title,
+            AppError::UpstreamTimeout(_) => "Upstream timed out",
+            AppError::Config(_) => "Service misconfigured",
+            AppError::Internal(_) => "Internal error",
+        }
+    }
+
+    // Specific to this occurrence
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Validation { message, .. } => message,
+            AppError::Upstream(upstream) => &upstream.message,
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::UpstreamTimeout(message)
+            | AppError::Config(message)
+            | AppError::Internal(message) => message,
+        }
+    }
+
+    pub fn details(&self) -> Option<&Value> {
+        match self {
+            AppError::Validation { details, .. } => details.as_ref(),
+            AppError::Upstream(upstream) => upstream.details.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.code(), self.status().as_u16(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        // 错误处理逻辑
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        let mut response = ProblemDetails::from(&self).into_response();
+        if let AppError::Upstream(upstream) = self {
+            if let Some(retry_after) = upstream.retry_after {
+                response.headers_mut().insert(RETRY_AFTER, retry_after);
+            }
+        }
+        response
+    }
+}
+
+// --- Conversions ---
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::validation(rejection.body_text())
+    }
+}
+
+impl From<ClientAuthError> for AppError {
+    fn from(e: ClientAuthError) -> Self {
+        match e {
+            ClientAuthError::Unauthorized => AppError::Unauthorized("Invalid API key".to_string()),
+            ClientAuthError::Forbidden(message) => AppError::Forbidden(message),
+        }
+    }
+}
+
+impl From<ConfigError> for AppError {
+    fn from(e: ConfigError) -> Self {
+        AppError::Config(e.to_string())
+    }
+}
+
+// Failures talking to an upstream: timeouts get their own status, everything
+// else (connect errors, unreadable bodies) is a bad gateway
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AppError::UpstreamTimeout(format!("Upstream request timed out: {}", e))
+        } else if e.is_decode() {
+            AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("Failed to parse upstream response: {}", e))
+        } else {
+            AppError::upstream("upstream_unreachable", "Upstream unreachable", format!("Upstream request failed: {}", e))
+        }
+    }
+}
+
+// serde_json errors come from JSON the caller sent us
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::validation(format!("Invalid JSON: {}", e))
+    }
+}
+
+// jsonwebtoken errors come from our own keys, never from the caller
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        AppError::Internal(format!("JWT error: {}", e))
     }
 }
+// --- End Conversions ---
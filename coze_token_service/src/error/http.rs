@@ -1 +1,60 @@
-// HTTP error conversion logic
+// HTTP error conversion logic: every AppError is sent as an RFC 7807 problem document
+use axum::{
+    Json,
+    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::error::AppError;
+use crate::routes::request_id::current_request_id;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// Problem types are URNs named after the error code, e.g. urn:coze-token-service:problem:forbidden
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:coze-token-service:problem:";
+
+// `code` and `details` are extension members; `instance` is the request id,
+// the same value as the X-Request-Id response header
+#[derive(Debug, Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl From<&AppError> for ProblemDetails {
+    fn from(e: &AppError) -> Self {
+        ProblemDetails {
+            problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, e.code()),
+            title: e.title().to_string(),
+            status: e.status().as_u16(),
+            detail: e.message().to_string(),
+            instance: current_request_id(),
+            code: e.code().to_string(),
+            details: e.details().cloned(),
+        }
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, Json(self)).into_response();
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+// Router fallback, so unknown paths get a problem document too
+pub async fn not_found() -> AppError {
+    AppError::NotFound("Not Found".to_string())
+}
@@ -0,0 +1,45 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::feishu::model::FeishuTokenType;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FeishuCacheKey {
+    pub app_id: String,
+    pub token_type: FeishuTokenType,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedFeishuToken {
+    pub token: String,
+    pub expires_at: i64, // Unix timestamp (seconds)
+}
+
+// Same single-flight scheme as the Coze TokenCache: whoever holds a key's
+// mutex fetches the token while concurrent callers wait and reuse it
+pub type FeishuCacheSlot = Arc<AsyncMutex<Option<CachedFeishuToken>>>;
+
+pub struct FeishuTokenCache {
+    slots: Mutex<HashMap<FeishuCacheKey, FeishuCacheSlot>>,
+    pub refresh_margin_seconds: i64,
+}
+
+impl FeishuTokenCache {
+    pub fn new(refresh_margin_seconds: i64) -> Self {
+        FeishuTokenCache {
+            slots: Mutex::new(HashMap::new()),
+            refresh_margin_seconds,
+        }
+    }
+
+    pub fn slot(&self, key: FeishuCacheKey) -> FeishuCacheSlot {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.entry(key).or_default().clone()
+    }
+
+    // A cached token is used until `refresh_margin_seconds` before it expires;
+    // Feishu issues a new one once less than 30 minutes remain
+    pub fn is_fresh(&self, cached: &CachedFeishuToken, now: i64) -> bool {
+        now < cached.expires_at - self.refresh_margin_seconds
+    }
+}
@@ -0,0 +1,86 @@
+use axum::http::StatusCode;
+use serde_json::json;
+use tracing::{info, error, debug};
+
+use crate::auth::model::AppConfig;
+use crate::error::error::{AppError, UpstreamError};
+use crate::feishu::cache::{CachedFeishuToken, FeishuCacheKey};
+use crate::feishu::model::{FeishuApp, FeishuTokenRequest, FeishuTokenResponse};
+
+// Feishu's documented lifetime, used when a response leaves `expire` out
+const DEFAULT_EXPIRE_SECONDS: i64 = 7200;
+
+// A failed token request, reported with Feishu's own code and message as
+// details: {"upstream_status", "feishu": {code, msg}}
+fn feishu_error(app: &FeishuApp, upstream_status: StatusCode, code: Option<i64>, msg: String) -> AppError {
+    let (status, error_code, title) = if upstream_status == StatusCode::TOO_MANY_REQUESTS {
+        (StatusCode::TOO_MANY_REQUESTS, "feishu_rate_limited", "Feishu rate limit exceeded")
+    } else {
+        (StatusCode::BAD_GATEWAY, "feishu_auth_error", "Feishu rejected the app credentials")
+    };
+    AppError::Upstream(Box::new(UpstreamError {
+        status,
+        code: error_code,
+        title,
+        message: format!("Feishu app {}: {}", app.app_id, msg),
+        details: Some(json!({
+            "upstream_status": upstream_status.as_u16(),
+            "feishu": { "code": code, "msg": msg },
+        })),
+        retry_after: None,
+    }))
+}
+
+// A valid access token for `app`, from the cache or fetched from Feishu.
+// Holding the slot lock across the request makes concurrent callers share one fetch.
+pub async fn feishu_access_token(config: &AppConfig, app: &FeishuApp) -> Result<String, AppError> {
+    let slot = config.feishu_token_cache.slot(FeishuCacheKey {
+        app_id: app.app_id.clone(),
+        token_type: app.token_type,
+    });
+    let mut cached = slot.lock().await;
+
+    let now = config.clock.now();
+    if let Some(entry) = cached.as_ref().filter(|entry| config.feishu_token_cache.is_fresh(entry, now)) {
+        debug!("Using cached Feishu {:?} token for app {} (expires at {})", app.token_type, app.app_id, entry.expires_at);
+        return Ok(entry.token.clone());
+    }
+
+    let response = exchange_feishu_token(config, app).await?;
+    let token = response.token(app.token_type)
+        .ok_or_else(|| AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("Feishu returned no {:?} token for app {}", app.token_type, app.app_id)))?
+        .to_string();
+    let expires_at = now + response.expire.unwrap_or(DEFAULT_EXPIRE_SECONDS);
+    info!("Fetched Feishu {:?} token for app {} (expires at {})", app.token_type, app.app_id, expires_at);
+    *cached = Some(CachedFeishuToken { token: token.clone(), expires_at });
+    Ok(token)
+}
+
+// Exchanges the app's id and secret for a token at FEISHU_BASE_URL
+pub async fn exchange_feishu_token(config: &AppConfig, app: &FeishuApp) -> Result<FeishuTokenResponse, AppError> {
+    let url = format!("{}{}", config.feishu_base_url, app.token_type.path());
+    info!("Requesting Feishu {:?} token for app {} at {}", app.token_type, app.app_id, url);
+
+    let response = config.http_client
+        .post(&url)
+        .json(&FeishuTokenRequest { app_id: &app.app_id, app_secret: &app.app_secret })
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Failed to call Feishu auth API: {}", e);
+            AppError::from(e)
+        })?;
+
+    let status = response.status();
+    let text = response.text().await.map_err(AppError::from)?;
+    let body = match serde_json::from_str::<FeishuTokenResponse>(&text) {
+        Ok(body) => body,
+        Err(_) if !status.is_success() => return Err(feishu_error(app, status, None, text)),
+        Err(e) => return Err(AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("Failed to parse Feishu auth response: {}", e))),
+    };
+    if !status.is_success() || body.code != 0 {
+        error!("Feishu auth API returned error for app {} ({}, code {}): {}", app.app_id, status, body.code, body.msg);
+        return Err(feishu_error(app, status, Some(body.code), body.msg));
+    }
+    Ok(body)
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Which Feishu token an app exchanges its secret for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeishuTokenType {
+    #[default]
+    Tenant, // tenant_access_token: acts as the app within its tenant, e.g. for bitable
+    App,    // app_access_token
+}
+
+impl FeishuTokenType {
+    pub fn path(&self) -> &'static str {
+        match self {
+            FeishuTokenType::Tenant => "/open-apis/auth/v3/tenant_access_token/internal",
+            FeishuTokenType::App => "/open-apis/auth/v3/app_access_token/internal",
+        }
+    }
+}
+
+// A Feishu self-built app as configured on a credential; the secret is given
+// inline or in an environment variable
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeishuAppConfig {
+    pub app_id: String,
+    #[serde(default)]
+    pub app_secret: Option<String>,
+    #[serde(default)]
+    pub app_secret_env: Option<String>,
+    #[serde(default)]
+    pub token_type: FeishuTokenType,
+}
+
+#[derive(Clone)]
+pub struct FeishuApp {
+    pub app_id: String,
+    pub app_secret: String,
+    pub token_type: FeishuTokenType,
+}
+
+// Keeps the secret out of logs
+impl fmt::Debug for FeishuApp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeishuApp").field("app_id", &self.app_id).field("token_type", &self.token_type).finish()
+    }
+}
+
+impl FeishuApp {
+    pub fn from_config(config: FeishuAppConfig) -> Result<Self, String> {
+        if config.app_id.is_empty() {
+            return Err("feishu_app.app_id must not be empty".to_string());
+        }
+        let app_secret = match (config.app_secret, config.app_secret_env) {
+            (Some(secret), None) => secret,
+            (None, Some(var)) => std::env::var(&var)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("{} is not set (app_secret_env of Feishu app {})", var, config.app_id))?,
+            _ => return Err(format!("Feishu app {} needs exactly one of app_secret and app_secret_env", config.app_id)),
+        };
+        Ok(FeishuApp { app_id: config.app_id, app_secret, token_type: config.token_type })
+    }
+}
+
+// Body of the Feishu internal app auth endpoints
+#[derive(Serialize)]
+pub struct FeishuTokenRequest<'a> {
+    pub app_id: &'a str,
+    pub app_secret: &'a str,
+}
+
+// Feishu answers HTTP 200 for most failures; `code` is 0 only on success
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeishuTokenResponse {
+    pub code: i64,
+    #[serde(default)]
+    pub msg: String,
+    #[serde(default)]
+    pub tenant_access_token: Option<String>,
+    #[serde(default)]
+    pub app_access_token: Option<String>,
+    #[serde(default)]
+    pub expire: Option<i64>, // Seconds from now, usually 7200
+}
+
+impl FeishuTokenResponse {
+    pub fn token(&self, token_type: FeishuTokenType) -> Option<&str> {
+        match token_type {
+            FeishuTokenType::Tenant => self.tenant_access_token.as_deref(),
+            FeishuTokenType::App => self.app_access_token.as_deref(),
+        }
+    }
+}
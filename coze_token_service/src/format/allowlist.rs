@@ -0,0 +1,182 @@
+use axum::http::{HeaderName, Method};
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+// The only destination /resend accepts when RESEND_ROUTES_FILE is not set
+const DEFAULT_ROUTE_NAME: &str = "feishu_bitable_batch_create";
+const DEFAULT_ROUTE_PATTERN: &str = r"^https://open\.feishu\.cn/open-apis/bitable/v1/apps/[^/]+/tables/[^/]+/records/batch_create";
+const DEFAULT_ROUTE_HOST: &str = "open.feishu.cn";
+
+fn default_methods() -> Vec<String> {
+    vec!["POST".to_string()]
+}
+
+// One destination /resend may forward to, as written in RESEND_ROUTES_FILE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResendRouteConfig {
+    pub name: String,
+    pub url_pattern: String, // Regex over the whole location; must start with ^
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+    pub host: String, // The location's host must be exactly this; the pattern alone could match a longer host
+    #[serde(default)]
+    pub allow_http: bool, // Otherwise only https locations are forwarded
+    #[serde(default)]
+    pub allow_private_addresses: bool, // Allows loopback, private and link-local destinations, e.g. our own services
+    #[serde(default)]
+    pub forward_headers: Vec<String>, // Inbound headers passed on to the destination; none by default
+    #[serde(default)]
+    pub credentials: Vec<String>, // Names from CREDENTIALS_FILE a payload may attach; none by default
+}
+
+#[derive(Deserialize)]
+struct ResendRoutesFile {
+    routes: Vec<ResendRouteConfig>,
+}
+
+// A validated route, ready to match locations against
+#[derive(Debug)]
+pub struct ResendRoute {
+    pub config: ResendRouteConfig,
+    pattern: Regex,
+    methods: Vec<Method>,
+    forward_headers: Vec<HeaderName>,
+}
+
+impl ResendRoute {
+    pub fn new(config: ResendRouteConfig) -> Result<Self, String> {
+        if config.name.is_empty() {
+            return Err("route name must not be empty".to_string());
+        }
+        // An unanchored pattern would match a location that merely mentions an allowed URL
+        if !config.url_pattern.starts_with('^') {
+            return Err(format!("url_pattern of route {} must start with ^", config.name));
+        }
+        let pattern = Regex::new(&config.url_pattern)
+            .map_err(|e| format!("Invalid url_pattern for route {}: {}", config.name, e))?;
+        if config.methods.is_empty() {
+            return Err(format!("Route {} must allow at least one method", config.name));
+        }
+        let methods = config.methods.iter()
+            .map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("Invalid method {} for route {}", method, config.name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if config.host.is_empty() {
+            return Err(format!("host of route {} must not be empty", config.name));
+        }
+        let forward_headers = config.forward_headers.iter()
+            .map(|name| HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid header {} in forward_headers of route {}", name, config.name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ResendRoute { config, pattern, methods, forward_headers })
+    }
+
+    fn default_route() -> Self {
+        ResendRoute::new(ResendRouteConfig {
+            name: DEFAULT_ROUTE_NAME.to_string(),
+            url_pattern: DEFAULT_ROUTE_PATTERN.to_string(),
+            methods: default_methods(),
+            host: DEFAULT_ROUTE_HOST.to_string(),
+            allow_http: false,
+            allow_private_addresses: false,
+            forward_headers: Vec::new(),
+            credentials: Vec::new(),
+        })
+        .expect("the default resend route is valid")
+    }
+
+    pub fn matches(&self, location: &Url) -> bool {
+        // Checked on the parsed authority, so "https://open.feishu.cn.evil.net" or
+        // "https://open.feishu.cn@evil.net" never pass as open.feishu.cn
+        let host_matches = location.host_str().is_some_and(|host| host.eq_ignore_ascii_case(&self.config.host));
+        host_matches && self.pattern.is_match(location.as_str())
+    }
+
+    pub fn allows_method(&self, method: &Method) -> bool {
+        self.methods.contains(method)
+    }
+
+    pub fn forwards_header(&self, name: &HeaderName) -> bool {
+        self.forward_headers.contains(name)
+    }
+
+    pub fn allows_credential(&self, name: &str) -> bool {
+        self.config.credentials.iter().any(|c| c == name)
+    }
+}
+
+// Reads and validates every route in a RESEND_ROUTES_FILE
+pub fn read_resend_routes_file(path: &str) -> Result<Vec<ResendRoute>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read resend routes file {}: {}", path, e))?;
+    let file: ResendRoutesFile = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse resend routes file {}: {}", path, e))?;
+
+    let mut routes: Vec<ResendRoute> = Vec::with_capacity(file.routes.len());
+    for config in file.routes {
+        if routes.iter().any(|route| route.config.name == config.name) {
+            return Err(format!("Duplicate resend route {}", config.name));
+        }
+        routes.push(ResendRoute::new(config)?);
+    }
+    Ok(routes)
+}
+
+// The destinations /resend may forward to. Loaded from RESEND_ROUTES_FILE when
+// set (and reloaded when it changes), otherwise only the Feishu bitable batch_create API.
+pub struct ResendRoutes {
+    routes: RwLock<Vec<Arc<ResendRoute>>>,
+    file: Option<String>,
+}
+
+impl ResendRoutes {
+    pub fn load(file: Option<String>) -> Result<Self, String> {
+        let routes = match file.as_deref() {
+            Some(path) => read_resend_routes_file(path)?,
+            None => vec![ResendRoute::default_route()],
+        };
+        info!("Loaded {} resend route(s)", routes.len());
+
+        Ok(ResendRoutes {
+            routes: RwLock::new(routes.into_iter().map(Arc::new).collect()),
+            file,
+        })
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    // Re-reads the routes file; the current routes stay in place if it is invalid
+    pub fn reload(&self) -> Result<usize, String> {
+        let Some(path) = self.file.as_deref() else {
+            return Err("RESEND_ROUTES_FILE is not configured".to_string());
+        };
+        let routes = read_resend_routes_file(path)?;
+        let count = routes.len();
+        *self.routes.write().unwrap_or_else(|e| e.into_inner()) = routes.into_iter().map(Arc::new).collect();
+        info!("Reloaded {} resend route(s) from {}", count, path);
+        Ok(count)
+    }
+
+    // The first route allowing `method` to `location`. The error says whether
+    // no route matched the location or none allowed the method.
+    pub fn find(&self, location: &Url, method: &Method) -> Result<Arc<ResendRoute>, String> {
+        let routes = self.routes.read().unwrap_or_else(|e| e.into_inner());
+        let matching: Vec<&Arc<ResendRoute>> = routes.iter().filter(|route| route.matches(location)).collect();
+        if matching.is_empty() {
+            return Err(format!("Location not allowed: {}", location));
+        }
+        matching.into_iter()
+            .find(|route| route.allows_method(method))
+            .cloned()
+            .ok_or_else(|| format!("Method {} is not allowed for location: {}", method, location))
+    }
+
+    pub fn list(&self) -> Vec<ResendRouteConfig> {
+        self.routes.read().unwrap_or_else(|e| e.into_inner()).iter().map(|route| route.config.clone()).collect()
+    }
+}
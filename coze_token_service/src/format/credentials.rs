@@ -0,0 +1,158 @@
+use axum::http::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::auth::model::AppConfig;
+use crate::error::error::AppError;
+use crate::feishu::handler::feishu_access_token;
+use crate::feishu::model::{FeishuApp, FeishuAppConfig};
+
+// Replaces a credential's value wherever a destination echoes it back
+pub const REDACTED: &str = "[REDACTED]";
+
+// Hides a credential value in `text`: the whole value, and the secret after an
+// auth scheme such as "Bearer ", which a destination may echo on its own
+pub fn redact(text: &str, value: &str) -> String {
+    let redacted = text.replace(value, REDACTED);
+    match value.split_once(' ').map(|(_, secret)| secret.trim()) {
+        Some(secret) if !secret.is_empty() => redacted.replace(secret, REDACTED),
+        _ => redacted,
+    }
+}
+
+fn default_header() -> String {
+    "authorization".to_string()
+}
+
+// A secret /resend attaches to outbound requests, as written in CREDENTIALS_FILE.
+// The value is given inline or, to keep it out of the file, in an environment
+// variable; a feishu_app credential is "Bearer <token>" for that app's access token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialConfig {
+    pub name: String,
+    #[serde(default = "default_header")]
+    pub header: String, // Set on the outbound request, e.g. "Bearer ..." in Authorization
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub value_env: Option<String>,
+    #[serde(default)]
+    pub feishu_app: Option<FeishuAppConfig>,
+}
+
+#[derive(Deserialize)]
+struct CredentialsFile {
+    credentials: Vec<CredentialConfig>,
+}
+
+// What the admin API shows for a credential; never the value
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialSummary {
+    pub name: String,
+    pub header: String,
+    pub feishu_app_id: Option<String>, // Set for credentials backed by a Feishu app
+}
+
+// Where a credential's value comes from
+enum CredentialSource {
+    Static(HeaderValue), // Marked sensitive, so it prints as "Sensitive" in logs
+    Feishu(FeishuApp),   // Fetched and cached per app when used
+}
+
+pub struct Credential {
+    pub name: String,
+    pub header: HeaderName,
+    source: CredentialSource,
+}
+
+impl Credential {
+    fn new(config: CredentialConfig) -> Result<Self, String> {
+        if config.name.is_empty() {
+            return Err("credential name must not be empty".to_string());
+        }
+        let header = HeaderName::from_bytes(config.header.as_bytes())
+            .map_err(|_| format!("Invalid header {} for credential {}", config.header, config.name))?;
+        let value = match (config.value, config.value_env, config.feishu_app) {
+            (Some(value), None, None) => value,
+            (None, Some(var), None) => std::env::var(&var)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("{} is not set (value_env of credential {})", var, config.name))?,
+            (None, None, Some(app)) => {
+                let app = FeishuApp::from_config(app).map_err(|e| format!("Credential {}: {}", config.name, e))?;
+                return Ok(Credential { name: config.name, header, source: CredentialSource::Feishu(app) });
+            }
+            _ => return Err(format!("Credential {} needs exactly one of value, value_env and feishu_app", config.name)),
+        };
+        let value = sensitive_value(&value).map_err(|_| format!("Invalid header value for credential {}", config.name))?;
+        Ok(Credential { name: config.name, header, source: CredentialSource::Static(value) })
+    }
+
+    // The header value to send now; Feishu-backed credentials may fetch a token
+    pub async fn resolve(&self, config: &AppConfig) -> Result<HeaderValue, AppError> {
+        match &self.source {
+            CredentialSource::Static(value) => Ok(value.clone()),
+            CredentialSource::Feishu(app) => {
+                let token = feishu_access_token(config, app).await?;
+                sensitive_value(&format!("Bearer {}", token))
+                    .map_err(|_| AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("Feishu returned an unusable token for app {}", app.app_id)))
+            }
+        }
+    }
+
+    pub fn summary(&self) -> CredentialSummary {
+        let feishu_app_id = match &self.source {
+            CredentialSource::Feishu(app) => Some(app.app_id.clone()),
+            CredentialSource::Static(_) => None,
+        };
+        CredentialSummary { name: self.name.clone(), header: self.header.to_string(), feishu_app_id }
+    }
+}
+
+fn sensitive_value(value: &str) -> Result<HeaderValue, axum::http::header::InvalidHeaderValue> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+// Named credentials /resend payloads may refer to, from CREDENTIALS_FILE.
+// Callers only ever see the names; routes list the ones they may be sent to.
+#[derive(Default)]
+pub struct Credentials {
+    credentials: HashMap<String, Arc<Credential>>,
+}
+
+impl Credentials {
+    pub fn load(file: Option<String>) -> Result<Self, String> {
+        let Some(path) = file else {
+            return Ok(Credentials::default());
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read credentials file {}: {}", path, e))?;
+        let file: CredentialsFile = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse credentials file {}: {}", path, e))?;
+
+        let mut credentials = HashMap::new();
+        for config in file.credentials {
+            let credential = Credential::new(config)?;
+            if credentials.contains_key(&credential.name) {
+                return Err(format!("Duplicate credential {}", credential.name));
+            }
+            credentials.insert(credential.name.clone(), Arc::new(credential));
+        }
+        info!("Loaded {} credential(s) from {}", credentials.len(), path);
+        Ok(Credentials { credentials })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Credential>> {
+        self.credentials.get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<CredentialSummary> {
+        let mut summaries: Vec<CredentialSummary> = self.credentials.values().map(|c| c.summary()).collect();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+}
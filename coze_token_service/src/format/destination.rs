@@ -0,0 +1,193 @@
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use reqwest::{redirect, Client, Url};
+use serde_json::Value;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, error};
+
+use crate::error::error::AppError;
+use crate::format::allowlist::{ResendRoute, ResendRoutes};
+
+// Redirects followed for one /resend call, each checked like the original location
+pub const MAX_REDIRECTS: usize = 5;
+
+// Headers dropped when a redirect leaves the original host, as browsers and reqwest do
+const CROSS_HOST_SENSITIVE_HEADERS: [header::HeaderName; 3] = [header::AUTHORIZATION, header::COOKIE, header::PROXY_AUTHORIZATION];
+
+// Loopback, private, link-local, shared (CGNAT), documentation, benchmarking,
+// multicast and reserved ranges are all internal or meaningless as a destination
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(a == 0
+        || a == 10
+        || a == 127
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 169 && b == 254)
+        || (a == 172 && (16..32).contains(&b))
+        || (a == 192 && b == 0 && (c == 0 || c == 2))
+        || (a == 192 && b == 168)
+        || (a == 198 && (18..20).contains(&b))
+        || (a == 198 && b == 51 && c == 100)
+        || (a == 203 && b == 0 && c == 113)
+        || a >= 224)
+}
+
+fn embedded_ipv4(high: u16, low: u16) -> Ipv4Addr {
+    let [a, b] = high.to_be_bytes();
+    let [c, d] = low.to_be_bytes();
+    Ipv4Addr::new(a, b, c, d)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped, IPv4-compatible (::/96, which includes :: and ::1), NAT64 and
+    // 6to4 addresses reach the embedded IPv4 address
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ipv4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0, 0, 0, 0, 0, 0] || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_public_ipv4(embedded_ipv4(segments[6], segments[7]));
+    }
+    if segments[0] == 0x2002 {
+        return is_public_ipv4(embedded_ipv4(segments[1], segments[2]));
+    }
+    !(ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        || (segments[0] & 0xffc0) == 0xfec0 // Site-local (deprecated)
+        || (segments[0] == 0x2001 && segments[1] == 0) // Teredo, which tunnels to an arbitrary IPv4 address
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8) // Documentation
+        || segments[..4] == [0x100, 0, 0, 0]) // Discard-only
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+// The address of a location whose host is an IP literal (IPv6 ones come bracketed)
+fn ip_literal(location: &Url) -> Option<IpAddr> {
+    location.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+// Resolves the location's host and checks every address, so a name that
+// resolves to an internal address (directly or by DNS rebinding) is refused
+async fn resolve_destination(location: &Url, route: &ResendRoute) -> Result<Vec<SocketAddr>, AppError> {
+    let scheme_allowed = location.scheme() == "https" || (location.scheme() == "http" && route.config.allow_http);
+    if !scheme_allowed {
+        return Err(AppError::Forbidden(format!("Scheme {} is not allowed for route {}", location.scheme(), route.config.name)));
+    }
+    let host = location.host_str().ok_or_else(|| AppError::validation("location has no host"))?;
+    let port = location.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = match ip_literal(location) {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((host, port)).await
+            .map_err(|e| AppError::upstream("upstream_unreachable", "Upstream unreachable", format!("Failed to resolve {}: {}", host, e)))?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(AppError::upstream("upstream_unreachable", "Upstream unreachable", format!("{} did not resolve to any address", host)));
+    }
+
+    if !route.config.allow_private_addresses {
+        if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+            error!("Refusing to forward to {}: {} resolves to {}", location, host, addr.ip());
+            return Err(AppError::Forbidden(format!("Location resolves to a non-public address: {}", host)));
+        }
+    }
+    Ok(addrs)
+}
+
+// A client that connects only to the addresses that were checked and never
+// follows redirects on its own
+fn pinned_client(location: &Url, addrs: &[SocketAddr], timeout: Duration) -> Result<Client, AppError> {
+    // A proxy would resolve the host itself and bypass the checked addresses
+    let mut builder = Client::builder()
+        .no_proxy()
+        .redirect(redirect::Policy::none())
+        .timeout(timeout);
+    if let (Some(domain), None) = (location.host_str(), ip_literal(location)) {
+        builder = builder.resolve_to_addrs(domain, addrs);
+    }
+    builder.build().map_err(|e| AppError::Internal(format!("Failed to build HTTP client: {}", e)))
+}
+
+// The request to send after a redirect: where to, with which method and whether
+// the body is kept (only 307 and 308 preserve both)
+fn redirect_target(current: &Url, method: &Method, status: StatusCode, headers: &HeaderMap) -> Result<(Url, Method, bool), AppError> {
+    let location = headers.get(header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("{} redirected without a Location", current)))?;
+    let next = current.join(location)
+        .map_err(|e| AppError::upstream("upstream_invalid_response", "Invalid upstream response", format!("Invalid redirect Location {}: {}", location, e)))?;
+
+    let preserve = matches!(status, StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT);
+    let next_method = if preserve || *method == Method::HEAD { method.clone() } else { Method::GET };
+    Ok((next, next_method, preserve))
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme() && a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default()
+}
+
+// A request /resend is about to send
+pub struct Outbound {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Value>, // Sent as JSON
+}
+
+// Sends `outbound` to a destination already matched by `route`. Every hop is
+// checked before connecting: scheme, resolved addresses and, for redirects,
+// the routes allowlist again.
+pub async fn send_to_destination(routes: &ResendRoutes, route: Arc<ResendRoute>, mut outbound: Outbound, timeout: Duration) -> Result<reqwest::Response, AppError> {
+    let mut route = route;
+    for _ in 0..=MAX_REDIRECTS {
+        let addrs = resolve_destination(&outbound.url, &route).await?;
+        let client = pinned_client(&outbound.url, &addrs, timeout)?;
+        let mut request = client.request(outbound.method.clone(), outbound.url.clone()).headers(outbound.headers.clone());
+        if let Some(body) = &outbound.body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(|e| {
+            error!("Request forwarding failed: {}", e);
+            AppError::from(e)
+        })?;
+
+        let status = response.status();
+        if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+
+        let (next, method, preserve) = redirect_target(&outbound.url, &outbound.method, status, response.headers())?;
+        route = routes.find(&next, &method).map_err(|message| {
+            error!("Redirect refused: {}", message);
+            AppError::Forbidden(format!("Redirect refused: {}", message))
+        })?;
+        info!("Following {} redirect to {} (route {})", status, next, route.config.name);
+        if !same_origin(&outbound.url, &next) {
+            for name in CROSS_HOST_SENSITIVE_HEADERS {
+                outbound.headers.remove(name);
+            }
+            // Injected credentials, whatever header they use
+            let sensitive: Vec<header::HeaderName> = outbound.headers.iter()
+                .filter(|(_, value)| value.is_sensitive())
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in sensitive {
+                outbound.headers.remove(name);
+            }
+        }
+        if !preserve {
+            outbound.body = None;
+        }
+        outbound.url = next;
+        outbound.method = method;
+    }
+    Err(AppError::upstream("upstream_too_many_redirects", "Too many redirects", format!("More than {} redirects", MAX_REDIRECTS)))
+}
@@ -1,14 +1,67 @@
 use axum::{
     Json,
-    response::{IntoResponse},
-    http::StatusCode,
-    http::HeaderMap,
+    extract::{rejection::JsonRejection, State},
+    response::{IntoResponse, Response},
+    http::{header::CONTENT_TYPE, HeaderMap, Method, StatusCode},
 };
+use reqwest::Url;
 use serde_json::Value;
-use regex::Regex;
-use reqwest::Client;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, error, debug, trace}; // Import tracing macros
 
+use crate::auth::clients::ClientRoute;
+use crate::auth::handler::authenticate_client;
+use crate::auth::model::AppConfig;
+use crate::error::error::{AppError, UpstreamError};
+use crate::format::destination::{send_to_destination, Outbound};
+use crate::format::credentials::redact;
+use crate::format::headers::outbound_headers;
+use crate::models::response::ApiResponse;
+use crate::routes::request_id::raw_response_requested;
+
+// Header carrying the caller's API key for this service
+const API_KEY_HEADER: &str = "x-api-key";
+
+// The payload's `method`, POST when absent
+fn resend_method(payload: &Value) -> Result<Method, AppError> {
+    match &payload["method"] {
+        Value::Null => Ok(Method::POST),
+        Value::String(method) => Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| AppError::validation(format!("Invalid method: {}", method))),
+        _ => Err(AppError::validation("method must be a string")),
+    }
+}
+
+// Appends the payload's `query` object to the location. Values may be strings,
+// numbers or booleans; an array repeats the parameter once per element.
+fn append_query(location: &mut Url, query: &Value) -> Result<(), AppError> {
+    let Some(query) = query.as_object() else {
+        return if query.is_null() { Ok(()) } else { Err(AppError::validation("query must be an object")) };
+    };
+    fn query_value(key: &str, value: &Value) -> Result<String, AppError> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            Value::Number(_) | Value::Bool(_) => Ok(value.to_string()),
+            _ => Err(AppError::validation(format!("query parameter {} must be a string, number, boolean or an array of those", key))),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (key, value) in query {
+        match value {
+            Value::Array(values) => for value in values {
+                pairs.push((key, query_value(key, value)?));
+            },
+            value => pairs.push((key, query_value(key, value)?)),
+        }
+    }
+    if !pairs.is_empty() {
+        location.query_pairs_mut().extend_pairs(pairs);
+    }
+    Ok(())
+}
+
 
 // Helper function to parse a simplified JSONPath string into segments
 fn parse_json_path(path: &str) -> Result<Vec<String>, String> {
@@ -122,30 +175,43 @@ fn traverse_and_modify(value: &mut Value, segments: &[String]) {
 
 #[axum::debug_handler]
 pub async fn resend_handler(
+    State(config): State<Arc<AppConfig>>,
     headers: HeaderMap,
-    Json(payload): Json<Value>,
-) -> impl IntoResponse {
+    payload: Result<Json<Value>, JsonRejection>,
+) -> Result<Response, AppError> {
     info!("Received request in resend_handler");
+    let Json(payload) = payload.map_err(|e| {
+        error!("Invalid resend request body: {}", e);
+        AppError::from(e)
+    })?;
+
+    // 0、校验调用方的API Key（X-Api-Key）
+    let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let client = authenticate_client(&config, api_key, ClientRoute::Resend)?;
+    info!("Resend authorized for client {}", client.id);
     debug!("Request payload: {:?}", payload);
 
-    // 1、检查location是否匹配环境参数中的可路由表中的所有正则；
-    // This requires access to environment parameters/config, which is not available in the handler directly.
-    // For now, I will add a placeholder check. A proper implementation would involve
-    // passing the allowed routes/regexes via state or a global config.
-    let location = payload["location"].as_str().unwrap_or_default();
+    // 1、检查location是否匹配可路由表（RESEND_ROUTES_FILE）中的路由；
+    let Some(location) = payload["location"].as_str() else {
+        error!("Resend request without a location");
+        return Err(AppError::validation("location is required"));
+    };
     info!("Attempting to forward request to: {}", location);
 
-    let allowed_routes: Vec<Regex> = vec![
-        // Placeholder regex - replace with actual allowed routes
-        Regex::new(r"^https://open\.feishu\.cn/open-apis/bitable/v1/apps/.*/tables/.*/records/batch_create").unwrap(),
-    ];
-
-    let is_allowed = allowed_routes.iter().any(|r| r.is_match(location));
-    if !is_allowed {
-        error!("Location not allowed: {}", location);
-        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "Location not allowed"}))).into_response();
+    let mut location_url = Url::parse(location)
+        .map_err(|e| AppError::validation(format!("location is not a valid URL: {}", e)))?;
+    let method = resend_method(&payload)?;
+    // GET and HEAD carry their parameters in `query`
+    if matches!(method, Method::GET | Method::HEAD) && !payload["params"].is_null() {
+        return Err(AppError::validation(format!("params cannot be sent with {}; use query", method)));
     }
-    debug!("Location is allowed");
+    let route = config.resend_routes.find(&location_url, &method).map_err(|message| {
+        error!("{}", message);
+        AppError::Forbidden(message)
+    })?;
+    debug!("{} {} is allowed by route {}", method, location, route.config.name);
+    // Routes match the location as given; `query` only adds parameters to it
+    append_query(&mut location_url, &payload["query"])?;
 
     // 2、根据命令对参数中的某个对象进行处理（例如json_parse从字符串转成合法的json对象）
     let mut mutable_payload = payload.clone(); // Clone the entire payload to modify
@@ -162,6 +228,8 @@ pub async fn resend_handler(
         Vec::new()
     };
 
+    // A bad path skips that command but not the request; the caller sees it in `errors`
+    let mut problems = Vec::new();
     for path in json_parse_paths {
         match parse_json_path(&path) {
             Ok(segments) => {
@@ -170,57 +238,89 @@ pub async fn resend_handler(
             }
             Err(e) => {
                 error!("Failed to parse JSON path {}: {}", path, e);
+                problems.push(AppError::validation(format!("Skipped json_parse path {}: {}", path, e)));
             }
         }
     }
 
     // 3、将所有headers处理后的params转发到location
-    let client = Client::new();
-
-    // Add headers from the incoming request, excluding host and content-length
-    let mut reqwest_headers = reqwest::header::HeaderMap::new();
-    for (key, value) in headers.iter() {
-        if key.as_str().to_lowercase() != "host" && key.as_str().to_lowercase() != "content-length" {
-            if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(key.as_ref()) {
-                    if let Ok(header_value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes()) {
-                    reqwest_headers.insert(header_name, header_value);
-                }
-            }
-        }
-    }
 
-    // Add headers from the payload
-    if let Some(payload_headers) = mutable_payload["headers"].as_object() {
-        for (key, value) in payload_headers {
-            if let Some(header_value_str) = value.as_str() {
-                if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(key.as_bytes()) {
-                    if let Ok(header_value) = reqwest::header::HeaderValue::from_str(header_value_str) {
-                        reqwest_headers.insert(header_name, header_value);
-                    }
-                }
+    // Inbound headers the route forwards, then the payload's headers on top
+    let mut reqwest_headers = outbound_headers(&headers, &route, &mutable_payload["headers"])?;
+
+    // A named credential is attached last; the payload cannot override or see it
+    let secret = match &payload["credential"] {
+        Value::Null => None,
+        Value::String(name) => {
+            let credential = config.credentials.get(name)
+                .ok_or_else(|| AppError::validation(format!("Unknown credential: {}", name)))?;
+            if !route.allows_credential(name) {
+                error!("Route {} does not allow credential {}", route.config.name, name);
+                return Err(AppError::Forbidden(format!("Credential {} is not allowed for route {}", name, route.config.name)));
+            }
+            if reqwest_headers.contains_key(&credential.header) {
+                return Err(AppError::validation(format!("Header {} is set by credential {}", credential.header, name)));
             }
+            info!("Attaching credential {} as {}", name, credential.header);
+            let value = credential.resolve(&config).await?;
+            reqwest_headers.insert(credential.header.clone(), value.clone());
+            Some(value)
         }
-    }
+        _ => return Err(AppError::validation("credential must be a string")),
+    };
 
     debug!("Forwarding with headers: {:?}", reqwest_headers);
     debug!("Forwarding with params: {:?}", mutable_payload["params"]);
 
-    // Send the request
-    let res = client.post(location).headers(reqwest_headers).json(&mutable_payload["params"]).send().await;
+    // Send the request; the destination and any redirects are checked hop by hop
+    let outbound = Outbound {
+        method,
+        url: location_url,
+        headers: reqwest_headers,
+        body: Some(mutable_payload["params"].clone()).filter(|params| !params.is_null()),
+    };
+    let timeout = Duration::from_secs(config.upstream_timeout_seconds);
+    let response = send_to_destination(&config.resend_routes, route, outbound, timeout).await?;
 
     // 4、获取发送的返回作为这个接口的返回返回
-    match res {
-        Ok(response) => {
-            let status = axum::http::StatusCode::from_u16(response.status().as_u16()).unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
-            info!("Forwarded request returned status: {}", status);
-            let resp_str = response.text().await.unwrap_or_else(|_| String::from("Failed to read response text"));
-            debug!("Response text: {:?}", resp_str);
-            let body = Json(serde_json::json!({"response": resp_str}));
-            (status, body).into_response()
-        }
-        Err(e) => {
-            error!("Request forwarding failed: {}", e);
-            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": format!("Request forwarding failed: {}", e)}))).into_response()
+    let status = response.status();
+    info!("Forwarded request returned status: {}", status);
+    let content_type = response.headers().get(CONTENT_TYPE).cloned();
+    let mut resp_str = response.text().await.map_err(|e| {
+        error!("Failed to read forwarded response: {}", e);
+        AppError::from(e)
+    })?;
+    // Never hand an attached credential back to the caller, e.g. from an echo endpoint
+    if let Some(secret) = secret.as_ref().and_then(|value| value.to_str().ok()) {
+        resp_str = redact(&resp_str, secret);
+    }
+    debug!("Response text: {:?}", resp_str);
+
+    if raw_response_requested() {
+        let mut raw = (status, resp_str).into_response();
+        if let Some(content_type) = content_type {
+            raw.headers_mut().insert(CONTENT_TYPE, content_type);
         }
+        return Ok(raw);
     }
+
+    // The destination's status is passed through; an error status is also listed in `errors`
+    if !status.is_success() {
+        problems.push(upstream_status_error(status, location));
+    }
+    let data = serde_json::from_str::<Value>(&resp_str).unwrap_or(Value::String(resp_str));
+    let body = problems.iter()
+        .fold(ApiResponse::new(data).with_upstream_status(status.as_u16()), |body, problem| body.with_error(problem));
+    Ok((status, body).into_response())
+}
+
+fn upstream_status_error(status: StatusCode, location: &str) -> AppError {
+    AppError::Upstream(Box::new(UpstreamError {
+        status,
+        code: "upstream_error_status",
+        title: "Upstream returned an error",
+        message: format!("{} answered {}", location, status),
+        details: None,
+        retry_after: None,
+    }))
 }
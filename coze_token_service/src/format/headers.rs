@@ -0,0 +1,74 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+
+use crate::error::error::AppError;
+use crate::format::allowlist::ResendRoute;
+
+// Connection-level headers (RFC 9110 section 7.6.1) that only apply to a single
+// hop; the client sets its own
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Headers meant for this service, or decided by the outbound request itself.
+// Never forwarded, even when a route lists them, and refused in the payload.
+const RESERVED_HEADERS: [&str; 6] = ["host", "content-length", "cookie", "x-api-key", "x-admin-key", "x-raw-response"];
+
+fn is_hop_by_hop(name: &HeaderName) -> bool {
+    HOP_BY_HOP_HEADERS.contains(&name.as_str())
+}
+
+fn is_reserved(name: &HeaderName) -> bool {
+    RESERVED_HEADERS.contains(&name.as_str())
+}
+
+// Headers the inbound Connection header declares hop-by-hop, e.g. "Connection: x-foo"
+fn connection_listed(inbound: &HeaderMap) -> Vec<HeaderName> {
+    inbound.get_all(header::CONNECTION).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect()
+}
+
+// The headers to send to a /resend destination. Inbound headers are forwarded
+// only when the route lists them in forward_headers; headers from the payload
+// are then applied on top, replacing forwarded headers of the same name.
+// Hop-by-hop and reserved headers are never forwarded, and naming one in the
+// payload is an error rather than silently dropped.
+pub fn outbound_headers(inbound: &HeaderMap, route: &ResendRoute, payload_headers: &Value) -> Result<HeaderMap, AppError> {
+    let connection_listed = connection_listed(inbound);
+    let mut outbound = HeaderMap::new();
+    for (name, value) in inbound {
+        if route.forwards_header(name) && !is_hop_by_hop(name) && !is_reserved(name) && !connection_listed.contains(name) {
+            outbound.append(name, value.clone());
+        }
+    }
+
+    let payload_headers = match payload_headers {
+        Value::Null => return Ok(outbound),
+        Value::Object(headers) => headers,
+        _ => return Err(AppError::validation("headers must be an object")),
+    };
+    for (key, value) in payload_headers {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|_| AppError::validation(format!("Invalid header name: {}", key)))?;
+        if is_hop_by_hop(&name) || is_reserved(&name) {
+            return Err(AppError::validation(format!("Header {} cannot be set in headers", name)));
+        }
+        let value = value.as_str()
+            .ok_or_else(|| AppError::validation(format!("Header {} must be a string", name)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| AppError::validation(format!("Invalid value for header {}", name)))?;
+        outbound.insert(name, value);
+    }
+    Ok(outbound)
+}
@@ -1 +1,5 @@
 pub mod handler;
+pub mod allowlist;
+pub mod destination;
+pub mod headers;
+pub mod credentials;
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod auth;
 pub mod config;
 pub mod error;
@@ -7,3 +8,4 @@ pub mod services;
 pub mod database;
 pub mod tests;
 pub mod format;
+pub mod feishu;
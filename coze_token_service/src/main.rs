@@ -1,7 +1,7 @@
-use coze_token_service::config;
+use coze_token_service::{config, services};
 use coze_token_service::routes::routing::create_router;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use tracing::info;
+use tracing::{info, error};
 
 #[tokio::main]
 async fn main() {
@@ -23,8 +23,19 @@ async fn main() {
     info!("Starting Coze Token Service");
     info!("Loading configuration...");
 
-    let config = config::config::load_config();
+    let config = match config::config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
     info!("Configuration loaded successfully");
+
+    info!("Starting background token refresh...");
+    services::refresh::spawn_token_refresher(config.clone());
+    services::key_rotation::spawn_keyring_watcher(config.clone());
+    services::resend_routes::spawn_resend_routes_watcher(config.clone());
     
     info!("Creating router...");
     let app = create_router().with_state(config.clone());
@@ -1 +1,67 @@
 // Global response models
+use axum::{
+    Json,
+    http::HeaderName,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::error::error::AppError;
+use crate::error::http::ProblemDetails;
+use crate::routes::request_id::{current_request_id, elapsed_ms, raw_response_requested};
+
+// Set to "true" to get `data` alone: the upstream body as-is for /token and
+// /resend, the bare object for every other endpoint
+pub const RAW_RESPONSE_HEADER: HeaderName = HeaderName::from_static("x-raw-response");
+
+// Body of every successful JSON response except the JWKS document:
+// {"data": ..., "meta": {"request_id", "elapsed_ms", "upstream_status"?}, "errors": [...]}
+// Failed requests are problem documents instead (see error::http).
+#[derive(Debug, Serialize)]
+pub struct ApiResponse<T> {
+    pub data: T,
+    pub meta: ResponseMeta,
+    pub errors: Vec<ProblemDetails>, // Problems that did not fail the request
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseMeta {
+    pub request_id: Option<String>,
+    pub elapsed_ms: u64, // Time spent in this service so far, upstream calls included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>, // Set when the response comes from an upstream call
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        ApiResponse {
+            data,
+            meta: ResponseMeta {
+                request_id: current_request_id(),
+                elapsed_ms: 0, // Filled in when the response is sent
+                upstream_status: None,
+            },
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_upstream_status(mut self, status: u16) -> Self {
+        self.meta.upstream_status = Some(status);
+        self
+    }
+
+    pub fn with_error(mut self, error: &AppError) -> Self {
+        self.errors.push(ProblemDetails::from(error));
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for ApiResponse<T> {
+    fn into_response(mut self) -> Response {
+        if raw_response_requested() {
+            return Json(self.data).into_response();
+        }
+        self.meta.elapsed_ms = elapsed_ms();
+        Json(self).into_response()
+    }
+}
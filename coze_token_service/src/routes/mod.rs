@@ -1,2 +1,3 @@
 // Global route aggregation
 pub mod routing;
+pub mod request_id;
@@ -0,0 +1,64 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+use crate::models::response::RAW_RESPONSE_HEADER;
+
+// Echoed on every response; a caller-supplied id is kept so logs can be
+// correlated across services
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+// Per-request facts the response models need but handlers do not pass around
+struct RequestContext {
+    id: String,
+    started: Instant,
+    raw_response: bool, // X-Raw-Response: true
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+// The id of the request being handled, for error bodies and logs
+pub fn current_request_id() -> Option<String> {
+    REQUEST_CONTEXT.try_with(|context| context.id.clone()).ok()
+}
+
+pub fn elapsed_ms() -> u64 {
+    REQUEST_CONTEXT.try_with(|context| context.started.elapsed().as_millis() as u64).unwrap_or_default()
+}
+
+pub fn raw_response_requested() -> bool {
+    REQUEST_CONTEXT.try_with(|context| context.raw_response).unwrap_or_default()
+}
+
+fn inbound_request_id(request: &Request) -> Option<String> {
+    let id = request.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+    valid.then(|| id.to_string())
+}
+
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = inbound_request_id(&request).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let raw_response = request.headers().get(&RAW_RESPONSE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    let span = info_span!("request", request_id = %id, method = %request.method(), path = %request.uri().path());
+
+    let context = RequestContext { id: id.clone(), started: Instant::now(), raw_response };
+    let mut response = REQUEST_CONTEXT.scope(context, next.run(request).instrument(span)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
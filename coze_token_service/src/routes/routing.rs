@@ -1,12 +1,34 @@
 use axum::Router;
 use std::sync::Arc;
 use crate::auth::model::AppConfig;
+use crate::error::http::not_found;
+use crate::routes::request_id::assign_request_id;
 use crate::format::handler::resend_handler;
-use crate::auth::handler::generate_and_exchange_token;
+use crate::auth::handler::{generate_and_exchange_token, jwks, mint_jwt, verify_jwt};
+use crate::admin::handler::{
+    token_refresh_status, list_keys, add_key, activate_key, retire_key, reload_keys,
+    list_clients, create_client, revoke_client, list_resend_routes, reload_resend_routes, list_credentials,
+};
 
 pub fn create_router() -> Router<Arc<AppConfig>> {
     Router::new()
     // 添加路由
     .route("/resend", axum::routing::post(resend_handler))
     .route("/token", axum::routing::post(generate_and_exchange_token))
+    .route("/token/jwt", axum::routing::post(mint_jwt))
+    .route("/token/verify", axum::routing::post(verify_jwt))
+    .route("/.well-known/jwks.json", axum::routing::get(jwks))
+    .route("/admin/token-refresh", axum::routing::get(token_refresh_status))
+    .route("/admin/keys", axum::routing::get(list_keys).post(add_key))
+    .route("/admin/keys/reload", axum::routing::post(reload_keys))
+    .route("/admin/keys/{kid}/activate", axum::routing::post(activate_key))
+    .route("/admin/keys/{kid}", axum::routing::delete(retire_key))
+    .route("/admin/clients", axum::routing::get(list_clients).post(create_client))
+    .route("/admin/clients/{id}", axum::routing::delete(revoke_client))
+    .route("/admin/resend-routes", axum::routing::get(list_resend_routes))
+    .route("/admin/resend-routes/reload", axum::routing::post(reload_resend_routes))
+    .route("/admin/credentials", axum::routing::get(list_credentials))
+    .fallback(not_found)
+    // Outermost, so every response (and error body) carries the request id
+    .layer(axum::middleware::from_fn(assign_request_id))
 }
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use crate::auth::keyring::read_keyring_file;
+use crate::auth::model::AppConfig;
+use crate::services::watch::spawn_file_watcher;
+
+// Re-reads JWT_KEYRING_FILE and applies it to the running keyring
+pub fn reload_keyring_file(config: &AppConfig) -> Result<(), String> {
+    let Some(path) = config.keyring_file.as_deref() else {
+        return Err("JWT_KEYRING_FILE is not configured".to_string());
+    };
+    let now = config.clock.now();
+    let (keys, active_kid) = read_keyring_file(path, &config.key_defaults, now)?;
+    config.keyring.sync_file_keys(keys, active_kid.as_deref(), now)
+}
+
+// Polls the keyring file for changes and drops keys whose grace window ended.
+// A broken file is logged and ignored so the current keys keep serving.
+pub fn spawn_keyring_watcher(config: Arc<AppConfig>) {
+    let reload_config = config.clone();
+    spawn_file_watcher(
+        "Keyring",
+        config.keyring_file.clone(),
+        config.keyring_watch_interval_seconds,
+        move || reload_keyring_file(&reload_config),
+        move || config.keyring.prune(config.clock.now()),
+    );
+}
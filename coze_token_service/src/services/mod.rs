@@ -1 +1,5 @@
 pub mod email;
+pub mod key_rotation;
+pub mod refresh;
+pub mod resend_routes;
+pub mod watch;
@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::{sync::{Arc, Mutex}, time::Duration};
+use tracing::{info, error, debug};
+
+use crate::auth::cache::{expires_at, CachedToken};
+use crate::auth::handler::exchange_token;
+use crate::auth::model::{AppConfig, SessionClaims};
+
+// A key id (and duration) whose Coze token is kept warm in the cache
+#[derive(Debug, Clone)]
+pub struct RefreshTarget {
+    pub kid: String,
+    pub duration_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RefreshOutcome {
+    Success,
+    Failure { error: String },
+}
+
+// Snapshot of one target's schedule, exposed through the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshStatus {
+    pub kid: String,
+    pub duration_seconds: u64,
+    pub next_refresh_at: Option<i64>,
+    pub last_refresh_at: Option<i64>,
+    pub last_outcome: Option<RefreshOutcome>,
+    pub consecutive_failures: u32,
+    pub token_expires_at: Option<i64>,
+}
+
+pub struct TokenRefresher {
+    targets: Vec<RefreshTarget>,
+    statuses: Mutex<Vec<RefreshStatus>>,
+    pub lead_seconds: i64,         // Refresh this long before the cache would consider a token stale
+    pub backoff_base_seconds: u64, // First retry delay after a failed exchange
+    pub backoff_max_seconds: u64,  // Upper bound for the exponential backoff
+}
+
+// Never reschedule more often than this, even for very short-lived tokens
+const MIN_REFRESH_INTERVAL_SECONDS: i64 = 30;
+
+impl TokenRefresher {
+    pub fn new(targets: Vec<RefreshTarget>, lead_seconds: i64, backoff_base_seconds: u64, backoff_max_seconds: u64) -> Self {
+        let statuses = targets.iter().map(|target| RefreshStatus {
+            kid: target.kid.clone(),
+            duration_seconds: target.duration_seconds,
+            next_refresh_at: None,
+            last_refresh_at: None,
+            last_outcome: None,
+            consecutive_failures: 0,
+            token_expires_at: None,
+        }).collect();
+
+        TokenRefresher {
+            targets,
+            statuses: Mutex::new(statuses),
+            lead_seconds,
+            backoff_base_seconds: backoff_base_seconds.max(1),
+            backoff_max_seconds,
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<RefreshStatus> {
+        self.statuses.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // base * 2^(failures - 1), capped at backoff_max_seconds
+    pub fn backoff_seconds(&self, consecutive_failures: u32) -> u64 {
+        let exponent = consecutive_failures.saturating_sub(1).min(32);
+        self.backoff_base_seconds
+            .saturating_mul(1u64 << exponent)
+            .min(self.backoff_max_seconds)
+    }
+
+    fn update_status(&self, index: usize, update: impl FnOnce(&mut RefreshStatus)) {
+        let mut statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(status) = statuses.get_mut(index) {
+            update(status);
+        }
+    }
+}
+
+// Parses `TOKEN_REFRESH_KEYS`, a comma separated list of `kid` or `kid:duration_seconds`
+pub fn parse_refresh_targets(value: &str, default_duration: u64) -> Vec<RefreshTarget> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.rsplit_once(':') {
+            Some((kid, duration)) if duration.parse::<u64>().is_ok() => RefreshTarget {
+                kid: kid.to_string(),
+                duration_seconds: duration.parse().unwrap_or(default_duration),
+            },
+            _ => RefreshTarget {
+                kid: entry.to_string(),
+                duration_seconds: default_duration,
+            },
+        })
+        .collect()
+}
+
+// Starts one background task per registered target
+pub fn spawn_token_refresher(config: Arc<AppConfig>) {
+    for index in 0..config.token_refresher.targets.len() {
+        let config = config.clone();
+        tokio::spawn(async move { refresh_loop(config, index).await });
+    }
+}
+
+async fn refresh_loop(config: Arc<AppConfig>, index: usize) {
+    let refresher = config.token_refresher.clone();
+    let target = refresher.targets[index].clone();
+    let mut consecutive_failures = 0u32;
+    info!("Starting background token refresh for kid {} ({}s)", target.kid, target.duration_seconds);
+
+    loop {
+        let result = refresh_once(&config, &target).await;
+        let now = config.clock.now();
+
+        let delay = match result {
+            Ok(token_expires_at) => {
+                consecutive_failures = 0;
+                let refresh_at = token_expires_at - config.token_cache.refresh_margin_seconds - refresher.lead_seconds;
+                let delay = (refresh_at - now).max(MIN_REFRESH_INTERVAL_SECONDS);
+                info!("Refreshed Coze token for kid {}, next refresh in {}s", target.kid, delay);
+                refresher.update_status(index, |status| {
+                    status.last_refresh_at = Some(now);
+                    status.last_outcome = Some(RefreshOutcome::Success);
+                    status.consecutive_failures = 0;
+                    status.token_expires_at = Some(token_expires_at);
+                    status.next_refresh_at = Some(now + delay);
+                });
+                delay as u64
+            }
+            Err(message) => {
+                consecutive_failures += 1;
+                let delay = refresher.backoff_seconds(consecutive_failures);
+                error!("Background refresh for kid {} failed ({} in a row), retrying in {}s: {}", target.kid, consecutive_failures, delay, message);
+                refresher.update_status(index, |status| {
+                    status.last_refresh_at = Some(now);
+                    status.last_outcome = Some(RefreshOutcome::Failure { error: message });
+                    status.consecutive_failures = consecutive_failures;
+                    status.next_refresh_at = Some(now + delay as i64);
+                });
+                delay
+            }
+        };
+
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+    }
+}
+
+// Exchanges a new token and stores it in the shared cache, returning its expiry
+async fn refresh_once(config: &AppConfig, target: &RefreshTarget) -> Result<i64, String> {
+    let now = config.clock.now();
+    let signing_key = config.keyring.get(&target.kid, now)
+        .ok_or_else(|| format!("Unknown or expired kid: {}", target.kid))?;
+
+    // The exchange runs without holding the cache slot, so callers keep being
+    // served the current token while the replacement is fetched
+    // Background refresh only covers unscoped tokens issued without session claims
+    let session = SessionClaims::default();
+    // Shared with callers held to the global maximum, like EXPECTED_COZE_API_KEY
+    let max_duration = config.duration_bounds.for_client(None).max_seconds;
+    let cache_key = config.token_cache.key(&target.kid, target.duration_seconds, max_duration, &session, None);
+    let response = match exchange_token(config, &signing_key, cache_key.duration_seconds, &session, None).await {
+        Ok((_, response)) => response,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let now = config.clock.now();
+    let token_expires_at = expires_at(now, response.expires_in);
+
+    let slot = config.token_cache.slot(cache_key, now);
+    *slot.lock().await = Some(CachedToken {
+        response,
+        expires_at: token_expires_at,
+    });
+    debug!("Stored refreshed token for kid {} in cache", target.kid);
+
+    Ok(token_expires_at)
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use crate::auth::model::AppConfig;
+use crate::services::watch::spawn_file_watcher;
+
+// Polls RESEND_ROUTES_FILE for changes. A broken file is logged and ignored
+// so the current routes keep serving.
+pub fn spawn_resend_routes_watcher(config: Arc<AppConfig>) {
+    let Some(path) = config.resend_routes.file().map(str::to_string) else {
+        return;
+    };
+    let interval_seconds = config.resend_routes_watch_interval_seconds;
+    spawn_file_watcher(
+        "Resend routes",
+        Some(path),
+        interval_seconds,
+        move || config.resend_routes.reload().map(|_| ()),
+        || {},
+    );
+}
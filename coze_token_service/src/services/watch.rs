@@ -0,0 +1,44 @@
+use std::time::{Duration, SystemTime};
+use tracing::{info, error};
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Polls `path` every `interval_seconds` (0 disables the watcher) and calls
+// `reload` when its modification time changes. A failed reload is logged and
+// retried on the next poll, so whatever was loaded before keeps serving.
+// `tick` runs after every poll, with or without a file.
+pub fn spawn_file_watcher<R, T>(name: &'static str, path: Option<String>, interval_seconds: u64, mut reload: R, mut tick: T)
+where
+    R: FnMut() -> Result<(), String> + Send + 'static,
+    T: FnMut() + Send + 'static,
+{
+    if interval_seconds == 0 {
+        info!("{} watcher disabled", name);
+        return;
+    }
+
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_seconds);
+        let mut last_modified = path.as_deref().and_then(modified_at);
+        info!("Watching {} every {}s", name, interval.as_secs());
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Some(path) = path.as_deref() {
+                let modified = modified_at(path);
+                if modified != last_modified {
+                    info!("{} file {} changed, reloading", name, path);
+                    match reload() {
+                        Ok(()) => last_modified = modified,
+                        Err(e) => error!("Failed to reload {} file, keeping the current version: {}", name, e),
+                    }
+                }
+            }
+
+            tick();
+        }
+    });
+}